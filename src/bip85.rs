@@ -0,0 +1,154 @@
+use bitcoin::{
+    PrivateKey,
+    network::constants::Network,
+    secp256k1::{Secp256k1, SecretKey},
+    util::bip32::{ChainCode, ChildNumber, DerivationPath, ExtendedPrivKey},
+};
+use bitcoin_hashes::{
+    hmac::{Hmac, HmacEngine},
+    sha512,
+    Hash,
+    HashEngine,
+};
+use bitcoin_wallet::mnemonic::Mnemonic;
+
+/*
+ *  BIP85 deterministic entropy from BIP32 keychains
+ */
+
+const PURPOSE: u32 = 83696968;
+
+pub enum App {
+    Bip39 { words: u32 },
+    Wif,
+    Xprv,
+    Hex { bytes: u32 },
+}
+
+impl App {
+    pub fn from_args(app: &str, words: u32, bytes: u32) -> Result<App, String> {
+        match app {
+            "bip39" => match words {
+                12 | 18 | 24 => Ok(App::Bip39 { words }),
+                _ => Err("BIP39 child seeds can only have 12, 18 or 24 words".to_string()),
+            },
+            "wif" => Ok(App::Wif),
+            "xprv" => Ok(App::Xprv),
+            "hex" => match bytes {
+                16..=64 => Ok(App::Hex { bytes }),
+                _ => Err("Hex entropy must be between 16 and 64 bytes".to_string()),
+            },
+            _ => Err(format!("Unknown BIP85 application {}, use bip39, wif, xprv or hex", app)),
+        }
+    }
+
+    pub fn path(&self, index: u32) -> DerivationPath {
+        let mut path: Vec<u32> = vec![PURPOSE];
+        match self {
+            // English is the only language supported, 0'
+            App::Bip39 { words } => path.extend(&[39, 0, *words]),
+            App::Wif => path.push(2),
+            App::Xprv => path.push(32),
+            App::Hex { bytes } => path.extend(&[128169, *bytes]),
+        };
+        path.push(index);
+        DerivationPath::from(path.into_iter().map(|i| ChildNumber::Hardened { index: i }).collect::<Vec<ChildNumber>>())
+    }
+}
+
+pub fn entropy(master: &ExtendedPrivKey, path: &DerivationPath) -> Result<Vec<u8>, String> {
+    let secp = Secp256k1::new();
+    let key = master.derive_priv(&secp, path).map_err(|e| e.to_string())?;
+    let mut engine = HmacEngine::<sha512::Hash>::new(b"bip-entropy-from-k");
+    engine.input(&key.private_key.key[..]);
+    Ok(Hmac::<sha512::Hash>::from_engine(engine).into_inner().to_vec())
+}
+
+pub fn to_mnemonic(entropy: &[u8], words: u32) -> Result<Mnemonic, String> {
+    let len = (words * 4 / 3) as usize;
+    Mnemonic::new(&entropy[0..len]).map_err(|e| e.to_string())
+}
+
+pub fn to_wif(entropy: &[u8], network: Network) -> Result<String, String> {
+    let key = SecretKey::from_slice(&entropy[0..32]).map_err(|e| e.to_string())?;
+    let privkey = PrivateKey {
+        compressed: true,
+        network,
+        key,
+    };
+    Ok(privkey.to_wif())
+}
+
+pub fn to_xprv(entropy: &[u8], network: Network) -> Result<String, String> {
+    let key = SecretKey::from_slice(&entropy[32..64]).map_err(|e| e.to_string())?;
+    let xprv = ExtendedPrivKey {
+        network,
+        depth: 0,
+        parent_fingerprint: Default::default(),
+        child_number: ChildNumber::Normal { index: 0 },
+        private_key: PrivateKey {
+            compressed: true,
+            network,
+            key,
+        },
+        chain_code: ChainCode::from(&entropy[0..32]),
+    };
+    Ok(xprv.to_string())
+}
+
+pub fn to_hex(entropy: &[u8], bytes: u32) -> String {
+    hex::encode(&entropy[0..bytes as usize])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    // Test vectors of the BIP85 specification
+    const MASTER: &str = "xprv9s21ZrQH143K2LBWUUQRFXhucrQqBpKdRRxNVq2zBqsx8HVqFk2uYo8kmbaLLHRdqtQpUm98uKfu3vca1LqdGhUtyoFnCNkfmXRyPXLjbKb";
+
+    fn derive(path: &str) -> Vec<u8> {
+        let master = ExtendedPrivKey::from_str(MASTER).unwrap();
+        entropy(&master, &DerivationPath::from_str(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn derived_entropy() {
+        assert_eq!(hex::encode(derive("m/83696968'/0'/0'")),
+            "efecfbccffea313214232d29e71563d941229afb4338c21f9517c41aaa0d16f00b83d2a09ef747e7a64e8e2bd5a14869e693da66ce94ac2da570ab7ee48618f7");
+        assert_eq!(hex::encode(derive("m/83696968'/0'/1'")),
+            "70c6e3e8ebee8dc4c0dbba66076819bb8c09672527c4277ca8729532ad711872218f826919f6b67218adde99018a6df9095ab2b58d803b5b93ec9802085a690e");
+    }
+
+    #[test]
+    fn bip39() {
+        let vectors = [
+            (12, "girl mad pet galaxy egg matter matrix prison refuse sense ordinary nose"),
+            (18, "near account window bike charge season chef number sketch tomorrow excuse sniff circle vital hockey outdoor supply token"),
+            (24, "puppy ocean match cereal symbol another shed magic wrap hammer bulb intact gadget divorce twin tonight reason outdoor destroy simple truth cigar social volcano"),
+        ];
+        for (words, mnemonic) in vectors.iter() {
+            let app = App::from_args("bip39", *words, 0).unwrap();
+            let master = ExtendedPrivKey::from_str(MASTER).unwrap();
+            let entropy = entropy(&master, &app.path(0)).unwrap();
+            assert_eq!(to_mnemonic(&entropy, *words).unwrap().to_string(), *mnemonic);
+        }
+    }
+
+    #[test]
+    fn wif() {
+        let master = ExtendedPrivKey::from_str(MASTER).unwrap();
+        let entropy = entropy(&master, &App::Wif.path(0)).unwrap();
+        assert_eq!(hex::encode(&entropy[0..32]), "7040bb53104f27367f317558e78a994ada7296c6fde36a364e5baf206e502bb1");
+        assert_eq!(to_wif(&entropy, Network::Bitcoin).unwrap(), "Kzyv4uF39d4Jrw2W7UryTHwZr1zQVNk4dAFyqE6BuMrMh1Za7uhp");
+    }
+
+    #[test]
+    fn xprv() {
+        let master = ExtendedPrivKey::from_str(MASTER).unwrap();
+        let entropy = entropy(&master, &App::Xprv.path(0)).unwrap();
+        assert_eq!(to_xprv(&entropy, Network::Bitcoin).unwrap(),
+            "xprv9s21ZrQH143K2srSbCSg4m4kLvPMzcWydgmKEnMmoZUurYuBuYG46c6P71UGXMzmriLzCCBvKQWBUv3vPB3m1SATMhp3uEjXHJ42jFg7myX");
+    }
+}
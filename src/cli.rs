@@ -11,10 +11,24 @@ pub struct Opts {
     pub export: bool,
     #[clap(long = "label", about = "Label for files with private and public encrypted master keys")]
     pub label: Option<String>,
+    #[clap(long = "datadir", value_name = "DIR", about = "Directory of wallet files (default $XDG_DATA_HOME/bitcoin-cli-wallet)")]
+    pub datadir: Option<String>,
     #[clap(short = 's', long, value_name = "N", about = "Use Shamir Shares")]
     pub shamir_shares: Option<u8>,
     #[clap(long = "mnemonic", value_name = "WORDS", about = "Inline mnemonic")]
     pub with_mnemonic: Option<String>,
+    #[clap(long = "insecure-permissions", about = "Load key files even if other users can access them")]
+    pub insecure_permissions: bool,
+    #[clap(long = "password-retries", value_name = "N", about = "Number of attempts to enter the password of encrypted files", default_value = "3")]
+    pub password_retries: u32,
+    #[clap(long = "passphrase", about = "Prompt for a BIP39 (25th word) or SLIP-0039 passphrase extending the seed")]
+    pub with_passphrase: bool,
+    #[clap(long = "xpub", value_name = "KEY", about = "Watch-only account extended public key, optionally prefixed with its [fingerprint/path] origin")]
+    pub xpub: Option<String>,
+    #[clap(long = "legacy-paths", about = "Derive keys the way older versions did, through the bitcoin-wallet sub account keys")]
+    pub legacy_paths: bool,
+    #[clap(long = "skip-backup-check", about = "Do not ask for words of new mnemonics and shares (regtest only)")]
+    pub skip_backup_check: bool,
     #[clap(subcommand)]
     pub subcommand: SubCommand,
 }
@@ -27,6 +41,14 @@ pub enum SubCommand {
     Master(MasterCommand),
     #[clap(about = "Fetch data from blockchain")]
     Get(GetCommand),
+    #[clap(about = "Manage accounts")]
+    Account(AccountCommand),
+    #[clap(about = "Manage standalone private keys")]
+    Keys(KeysCommand),
+    #[clap(about = "Manage wallets in the data directory")]
+    Wallets(WalletsCommand),
+    #[clap(about = "Create backups of the wallet")]
+    Backup(BackupCommand),
 }
 
 #[derive(Clap)]
@@ -43,6 +65,18 @@ pub enum MasterSubCommand {
     Recover(RecoverMasterSubCommand),
     #[clap(about = "Show master xpubkey (hex)")]
     Pubkey(PubkeyMasterSubCommand),
+    #[clap(about = "Show master fingerprint, network and account key origins")]
+    Info(InfoMasterSubCommand),
+    #[clap(about = "Derive deterministic child seeds and keys (BIP85)")]
+    Bip85(Bip85MasterSubCommand),
+    #[clap(about = "Rewrite legacy encrypted files with the current format")]
+    Migrate(MigrateMasterSubCommand),
+    #[clap(about = "Change the password of encrypted files")]
+    Passwd(PasswdMasterSubCommand),
+    #[clap(about = "Split the seed of an existing wallet into Shamir Secret-Sharing (SLIP-0039) Shares")]
+    Split(SplitMasterSubCommand),
+    #[clap(about = "Check Shamir Secret-Sharing (SLIP-0039) Shares and the fingerprint they recover")]
+    VerifyShares(VerifySharesMasterSubCommand),
 }
 
 #[derive(Clap)]
@@ -55,17 +89,66 @@ pub struct NewMasterSubCommand {
     pub max: u8,
     #[clap(short = 's', long, about = "Use Shamir Secret-Sharing SLIP-0039 (needs min & max for N-of-M rule)")]
     pub shamir_sharing: bool,
+    #[clap(long, value_name = "SCHEME", about = "Shamir groups as T:name=N/M,... e.g. 2:family=2/3,lawyers=1/1,safe=3/5")]
+    pub groups: Option<String>,
+    #[clap(long, about = "Mix dice rolls (1-6) into the entropy")]
+    pub dice: bool,
+    #[clap(long, about = "Mix coin flips (h/t) into the entropy")]
+    pub coins: bool,
+    #[clap(long = "entropy-hex", about = "Mix hex encoded entropy, entered at a hidden prompt, into the entropy")]
+    pub entropy_hex: bool,
+    #[clap(long, value_name = "HEIGHT", about = "Block height of the wallet creation, used as rescan starting point")]
+    pub birthday: Option<u64>,
 }
 
 #[derive(Clap)]
 pub struct RecoverMasterSubCommand {
     #[clap(short = 's', long, value_name = "N", about = "To recover from N number of Shamir Secret-Sharing (SLIP-0039) Shares")]
     pub shamir_shares: Option<u8>,
+    #[clap(long, value_name = "HEIGHT", about = "Block height of the wallet creation, used as rescan starting point")]
+    pub birthday: Option<u64>,
 }
 
 #[derive(Clap)]
 pub struct PubkeyMasterSubCommand {}
 
+#[derive(Clap)]
+pub struct InfoMasterSubCommand {
+    #[clap(long, value_name = "N", about = "Number of accounts to show", default_value = "1")]
+    pub accounts: u32,
+}
+
+#[derive(Clap)]
+pub struct MigrateMasterSubCommand {}
+
+#[derive(Clap)]
+pub struct PasswdMasterSubCommand {}
+
+#[derive(Clap)]
+pub struct VerifySharesMasterSubCommand {}
+
+#[derive(Clap)]
+pub struct SplitMasterSubCommand {
+    #[clap(long, value_name = "N", about = "Min value for N-of-M rules", required = false, default_value = "0")]
+    pub min: u8,
+    #[clap(long, value_name = "M", about = "Max value for N-of-M rules", required = false, default_value = "0")]
+    pub max: u8,
+    #[clap(long, value_name = "SCHEME", about = "Shamir groups as T:name=N/M,... e.g. 2:family=2/3,lawyers=1/1,safe=3/5")]
+    pub groups: Option<String>,
+}
+
+#[derive(Clap)]
+pub struct Bip85MasterSubCommand {
+    #[clap(long, value_name = "APP", about = "Application to derive (bip39, wif, xprv, hex)", default_value = "bip39")]
+    pub app: String,
+    #[clap(long, value_name = "W", about = "Number of words for bip39 (12, 18 or 24)", default_value = "12")]
+    pub words: u32,
+    #[clap(long, value_name = "B", about = "Number of bytes for hex (16-64)", default_value = "64")]
+    pub bytes: u32,
+    #[clap(long, value_name = "N", about = "Child index", default_value = "0")]
+    pub index: u32,
+}
+
 #[derive(Clap)]
 pub struct AddressCommand {
     #[clap(long = "type", value_name = "TYPE", about = "Type of address")]
@@ -76,6 +159,8 @@ pub struct AddressCommand {
     pub subaccount: u32,
     #[clap(short, long = "kix", value_name = "K", about = "Key derivation instance", default_value = "0")]
     pub kix: u32,
+    #[clap(long, value_name = "PATH", about = "Custom BIP32 derivation path (e.g. \"m/84'/0'/3'/0/7\") instead of account, sub account and key derivation instance")]
+    pub path: Option<String>,
     #[clap(long = "script-hash", value_name = "HASH", about = "The script hash to use for P2WSH addresses")]
     pub script_hash: Option<String>,
     #[clap(subcommand)]
@@ -86,10 +171,116 @@ pub struct AddressCommand {
 pub enum AddressSubCommand {
     #[clap(about = "Generate address")]
     Generate(GenerateAddressSubCommand),
+    #[clap(about = "Find the derivation path of an address")]
+    Find(FindAddressSubCommand),
+    #[clap(about = "Export the private key of an address as WIF")]
+    Privkey(PrivkeyAddressSubCommand),
+}
+
+#[derive(Clap)]
+pub struct GenerateAddressSubCommand {
+    #[clap(long, value_name = "TEXT", about = "Label to record for the address in the wallet file")]
+    pub note: Option<String>,
+}
+
+#[derive(Clap)]
+pub struct PrivkeyAddressSubCommand {
+    #[clap(long, about = "Show private key as QR code")]
+    pub qr: bool,
+}
+
+#[derive(Clap)]
+pub struct FindAddressSubCommand {
+    #[clap(value_name = "ADDR", about = "Address to look for")]
+    pub address: String,
+    #[clap(long, value_name = "A", about = "Highest account number to search", default_value = "10")]
+    pub max_account: u32,
+    #[clap(long, value_name = "K", about = "Highest key derivation instance to search", default_value = "100")]
+    pub max_index: u32,
+}
+
+#[derive(Clap)]
+pub struct AccountCommand {
+    #[clap(subcommand)]
+    pub subcommand: AccountSubCommand,
+}
+
+#[derive(Clap)]
+pub enum AccountSubCommand {
+    #[clap(about = "Export account extended public key with its origin")]
+    Xpub(XpubAccountSubCommand),
+}
+
+#[derive(Clap)]
+pub struct XpubAccountSubCommand {
+    #[clap(long = "type", value_name = "TYPE", about = "Type of address")]
+    pub address_type: Option<String>,
+    #[clap(short = 'n', long = "number", value_name = "ACCOUNT", about = "Account number", default_value = "0")]
+    pub account_number: u32,
+    #[clap(long, value_name = "ENCODING", about = "SLIP-0132 encoding (xpub, ypub, zpub, tpub, upub, vpub), inferred from type by default")]
+    pub encoding: Option<String>,
+    #[clap(long, value_name = "PATH", about = "Custom BIP32 account derivation path (e.g. \"m/84'/0'/3'\") instead of account number")]
+    pub path: Option<String>,
+}
+
+#[derive(Clap)]
+pub struct KeysCommand {
+    #[clap(subcommand)]
+    pub subcommand: KeysSubCommand,
+}
+
+#[derive(Clap)]
+pub enum KeysSubCommand {
+    #[clap(about = "Import a WIF private key into the wallet files")]
+    Import(ImportKeysSubCommand),
+}
+
+#[derive(Clap)]
+pub struct ImportKeysSubCommand {
+    #[clap(long = "type", value_name = "TYPE", about = "Type of address")]
+    pub address_type: Option<String>,
 }
 
 #[derive(Clap)]
-pub struct GenerateAddressSubCommand {}
+pub struct WalletsCommand {
+    #[clap(subcommand)]
+    pub subcommand: WalletsSubCommand,
+}
+
+#[derive(Clap)]
+pub enum WalletsSubCommand {
+    #[clap(about = "List wallets")]
+    List(ListWalletsSubCommand),
+    #[clap(about = "Show wallet file information")]
+    Info(InfoWalletsSubCommand),
+    #[clap(about = "Rename a wallet")]
+    Rename(RenameWalletsSubCommand),
+    #[clap(about = "Delete a wallet")]
+    Delete(DeleteWalletsSubCommand),
+}
+
+#[derive(Clap)]
+pub struct ListWalletsSubCommand {}
+
+#[derive(Clap)]
+pub struct InfoWalletsSubCommand {
+    #[clap(value_name = "LABEL", about = "Wallet label")]
+    pub label: String,
+}
+
+#[derive(Clap)]
+pub struct RenameWalletsSubCommand {
+    #[clap(value_name = "FROM", about = "Current wallet label")]
+    pub from: String,
+    #[clap(value_name = "TO", about = "New wallet label")]
+    pub to: String,
+}
+
+#[derive(Clap)]
+pub struct DeleteWalletsSubCommand {
+    #[clap(value_name = "LABEL", about = "Wallet label")]
+    pub label: String,
+}
 
 #[derive(Clap)]
 pub struct GetCommand {
@@ -115,6 +306,8 @@ pub struct CoinsGetSubCommand {
     pub subaccount: Option<u32>,
     #[clap(short, long = "kix", value_name = "K", about = "Key derivation instance")]
     pub kix: Option<u32>,
+    #[clap(long, value_name = "PATH", about = "Custom BIP32 derivation path (e.g. \"m/84'/0'/3'/0/7\") instead of account, sub account and key derivation instance")]
+    pub path: Option<String>,
     #[clap(long = "rescan", about = "Rescan the blockchain")]
     pub rescan: bool,
     #[clap(long, about = "Block height starting point to start scanning for coins")]
@@ -135,6 +328,30 @@ pub struct CoinsGetSubCommand {
     pub desc: bool,
 }
 
+#[derive(Clap)]
+pub struct BackupCommand {
+    #[clap(subcommand)]
+    pub subcommand: BackupSubCommand,
+}
+
+#[derive(Clap)]
+pub enum BackupSubCommand {
+    #[clap(about = "Render a printable sheet with the words, fingerprint and descriptor QR code")]
+    Paper(PaperBackupSubCommand),
+}
+
+#[derive(Clap)]
+pub struct PaperBackupSubCommand {
+    #[clap(long, value_name = "FILE", about = "Output file, the format is inferred from the extension (.svg, .pdf or .txt)")]
+    pub out: String,
+    #[clap(long, value_name = "FORMAT", about = "Output format (svg, pdf or ascii)")]
+    pub format: Option<String>,
+    #[clap(long = "type", value_name = "TYPE", about = "Type of address for the descriptors")]
+    pub address_type: Option<String>,
+    #[clap(short = 'n', long = "number", value_name = "ACCOUNT", about = "Account number for the descriptors", default_value = "0")]
+    pub account_number: u32,
+}
+
 pub fn validate_opts(opts: &Opts, _: bool) -> Result<&str, &str> {
     if opts.with_testnet && opts.with_regtest {
         return Err("testnet or regtest, only specify one.");
@@ -144,8 +361,46 @@ pub fn validate_opts(opts: &Opts, _: bool) -> Result<&str, &str> {
         return Err("Need to specify a label for export file");
     }
 
+    if opts.skip_backup_check && !opts.with_regtest {
+        return Err("Backup check can only be skipped on regtest");
+    }
+
+    if opts.password_retries == 0 {
+        return Err("Password retries must be at least 1");
+    }
+
+    if let Some(_) = opts.xpub {
+        if opts.with_passphrase {
+            return Err("Cannot specify both xpub and passphrase");
+        } else if let Some(_) = opts.with_mnemonic {
+            return Err("Cannot specify both xpub and mnemonic");
+        } else if let Some(_) = opts.shamir_shares {
+            return Err("Cannot specify both xpub and Shamir Shares");
+        } else if opts.export {
+            return Err("Cannot export a watch-only wallet");
+        } else if opts.legacy_paths {
+            return Err("Derivation paths are fixed by the extended public key");
+        }
+        match &opts.subcommand {
+            SubCommand::Address(cmd_opts) => {
+                if cmd_opts.account_number != 0 {
+                    return Err("Account number is fixed by the extended public key");
+                }
+            },
+            SubCommand::Get(_) => {},
+            _ => return Err("Extended public keys can only be used to generate addresses or fetch coins"),
+        }
+    }
+
     match &opts.subcommand {
         SubCommand::Address(cmd_opts) => {
+            if let Some(_) = cmd_opts.path {
+                if cmd_opts.account_number != 0 || cmd_opts.subaccount != 0 || cmd_opts.kix != 0 {
+                    return Err("Cannot specify a path with account, sub account or key derivation instance");
+                } else if let Some(_) = opts.xpub {
+                    return Err("Derivation paths are fixed by the extended public key");
+                }
+            }
             match &cmd_opts.subcommand {
                 AddressSubCommand::Generate(_) => {
                     if let Some(addr) = &cmd_opts.address_type {
@@ -153,13 +408,39 @@ pub fn validate_opts(opts: &Opts, _: bool) -> Result<&str, &str> {
                             return Err("P2WSH not implemented yet");
                         }
                     }
-                }
+                },
+                AddressSubCommand::Privkey(_) => {
+                    if let Some(_) = opts.xpub {
+                        return Err("Cannot export private keys from an extended public key");
+                    }
+                },
+                AddressSubCommand::Find(_) => {
+                    if let Some(addr) = &cmd_opts.address_type {
+                        if addr.to_lowercase() == "p2wsh" {
+                            return Err("P2WSH not implemented yet");
+                        }
+                    }
+                    if let Some(_) = cmd_opts.path {
+                        return Err("Cannot specify a path to find an address");
+                    } else if cmd_opts.account_number != 0 || cmd_opts.subaccount != 0 || cmd_opts.kix != 0 {
+                        return Err("Cannot specify account, sub account or key derivation instance to find an address");
+                    }
+                },
             }
         },
         SubCommand::Master(cmd_opts) => {
             match &cmd_opts.subcommand {
                 MasterSubCommand::New(sub_opts) => {
-                    if sub_opts.shamir_sharing {
+                    if sub_opts.dice as u8 + sub_opts.coins as u8 + sub_opts.entropy_hex as u8 > 1 {
+                        return Err("Only specify one of dice, coins or entropy hex");
+                    }
+                    if sub_opts.groups.is_some() {
+                        if !sub_opts.shamir_sharing {
+                            return Err("Groups need Shamir Sharing");
+                        } else if sub_opts.min != 0 || sub_opts.max != 0 {
+                            return Err("Cannot specify both groups and min & max");
+                        }
+                    } else if sub_opts.shamir_sharing {
                         if sub_opts.min == 0 || sub_opts.max == 0 {
                             return Err("Min and max need to be specified for Shamir Sharing");
                         } else if sub_opts.min > sub_opts.max {
@@ -175,6 +456,37 @@ pub fn validate_opts(opts: &Opts, _: bool) -> Result<&str, &str> {
                     }
                 },
                 MasterSubCommand::Pubkey(_) => {},
+                MasterSubCommand::Info(_) => {},
+                MasterSubCommand::Bip85(_) => {},
+                MasterSubCommand::Migrate(_) => {
+                    if opts.label == None {
+                        return Err("Need to specify the label of the wallet to migrate");
+                    }
+                },
+                MasterSubCommand::Passwd(_) => {
+                    if opts.label == None {
+                        return Err("Need to specify the label of the wallet to change its password");
+                    }
+                },
+                MasterSubCommand::VerifyShares(_) => {
+                    if let Some(_) = opts.with_mnemonic {
+                        return Err("Cannot specify a mnemonic to verify shares");
+                    }
+                },
+                MasterSubCommand::Split(sub_opts) => {
+                    if opts.label == None {
+                        return Err("Need to specify the label of the wallet to split");
+                    }
+                    if sub_opts.groups.is_some() {
+                        if sub_opts.min != 0 || sub_opts.max != 0 {
+                            return Err("Cannot specify both groups and min & max");
+                        }
+                    } else if sub_opts.min == 0 || sub_opts.max == 0 {
+                        return Err("Min and max need to be specified for Shamir Sharing");
+                    } else if sub_opts.min > sub_opts.max {
+                        return Err("Min should be less in value than max");
+                    }
+                },
             }
         },
         SubCommand::Get(sub_opts) => {
@@ -206,10 +518,22 @@ pub fn validate_opts(opts: &Opts, _: bool) -> Result<&str, &str> {
                             return Err("Cannot specify address and key derivation instance");
                         } else if let Some(_) = o.address_type {
                             return Err("Cannot specify address type, it will be inferred from the input address");
+                        } else if let Some(_) = o.path {
+                            return Err("Cannot specify address and path");
+                        }
+                    } else if let Some(_) = o.path {
+                        if let Some(_) = opts.xpub {
+                            return Err("Derivation paths are fixed by the extended public key");
+                        } else if o.account_number != None || o.subaccount != None || o.kix != None {
+                            return Err("Cannot specify a path with account, sub account or key derivation instance");
+                        }
+                    } else if let Some(_) = opts.xpub {
+                        if let Some(_) = o.account_number {
+                            return Err("Account number is fixed by the extended public key");
                         }
                     } else {
                         if o.account_number == None {
-                            return Err("Need to specify at least an address or an account number");
+                            return Err("Need to specify at least an address, an account number or a path");
                         }
                     }
                     if let Some(_) = o.limit {
@@ -220,6 +544,67 @@ pub fn validate_opts(opts: &Opts, _: bool) -> Result<&str, &str> {
                 },
             }
         },
+        SubCommand::Account(cmd_opts) => {
+            match &cmd_opts.subcommand {
+                AccountSubCommand::Xpub(sub_opts) => {
+                    if let Some(_) = sub_opts.path {
+                        if sub_opts.account_number != 0 {
+                            return Err("Cannot specify a path with account number");
+                        }
+                    }
+                    if let Some(addr) = &sub_opts.address_type {
+                        if addr.to_lowercase() == "p2wsh" {
+                            return Err("P2WSH not implemented yet");
+                        }
+                    }
+                    if let Some(enc) = &sub_opts.encoding {
+                        let mainnet = !opts.with_testnet && !opts.with_regtest;
+                        match enc.as_str() {
+                            "xpub" | "ypub" | "zpub" => {
+                                if !mainnet {
+                                    return Err("Use tpub, upub or vpub encodings for testnet and regtest");
+                                }
+                            },
+                            "tpub" | "upub" | "vpub" => {
+                                if mainnet {
+                                    return Err("Use xpub, ypub or zpub encodings for mainnet");
+                                }
+                            },
+                            _ => return Err("Invalid encoding, use one of xpub, ypub, zpub, tpub, upub or vpub"),
+                        }
+                    }
+                },
+            }
+        },
+        SubCommand::Wallets(_) => {},
+        SubCommand::Backup(cmd_opts) => {
+            match &cmd_opts.subcommand {
+                BackupSubCommand::Paper(sub_opts) => {
+                    if opts.label == None {
+                        return Err("Need to specify the label of the wallet to back up");
+                    }
+                    if let Some(addr) = &sub_opts.address_type {
+                        if addr.to_lowercase() == "p2wsh" {
+                            return Err("P2WSH not implemented yet");
+                        }
+                    }
+                },
+            }
+        },
+        SubCommand::Keys(cmd_opts) => {
+            match &cmd_opts.subcommand {
+                KeysSubCommand::Import(sub_opts) => {
+                    if opts.label == None {
+                        return Err("Need to specify the label of the wallet to import keys into");
+                    }
+                    if let Some(addr) = &sub_opts.address_type {
+                        if addr.to_lowercase() == "p2wsh" {
+                            return Err("P2WSH not implemented yet");
+                        }
+                    }
+                },
+            }
+        },
     }
     Ok("OK")
 }
\ No newline at end of file
@@ -1,7 +1,6 @@
 use bitcoincore_rpc::{
     Auth,
     Client,
-    Error,
     RpcApi,
     bitcoin::{
         Address,
@@ -42,12 +41,13 @@ impl Node {
         Ok(Self::new(r[1].to_string(), creds[0].to_string(), creds[1].to_string()))
     }
 
-    pub fn connect(&mut self, _: String) -> Result<(), Error> {
-        let client = Client::new(self.url.clone(), self.auth.clone())?;
-        let info = client.get_blockchain_info()?;
-        /*if info.chain != chain {
-            // ERR
-        }*/
+    // chain as reported by getblockchaininfo: main, test or regtest
+    pub fn connect(&mut self, chain: &str) -> Result<(), String> {
+        let client = Client::new(self.url.clone(), self.auth.clone()).map_err(|e| e.to_string())?;
+        let info = client.get_blockchain_info().map_err(|e| e.to_string())?;
+        if info.chain != chain {
+            return Err(format!("Node is running on {} but the wallet uses {}", info.chain, chain));
+        }
         self.client = Some(client);
         self.blocks = info.blocks as usize;
         Ok(())
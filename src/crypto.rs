@@ -1,38 +1,62 @@
-use bitcoin_wallet::{
-    account::MasterAccount,
-    error::Error,
-};
+use bitcoin_wallet::error::Error;
 use crypto::{
-    aes, blockmodes, buffer,
+    aead::{AeadDecryptor, AeadEncryptor},
+    aes, aes_gcm::AesGcm, blockmodes, buffer,
     buffer::{BufferResult, ReadBuffer, WriteBuffer},
     digest::Digest,
+    scrypt::{scrypt, ScryptParams},
     sha2::Sha256,
 };
+use rand::{RngCore, rngs::OsRng};
 use bitcoin::util::bip32::{ExtendedPrivKey, ExtendedPubKey};
 
+use crate::keys::ImportedKey;
 use crate::utils;
 
-pub fn import_encrypted_keys(prefix: &str, password: &str) -> (Vec<u8>, ExtendedPubKey) {
-    let pubfile = format!("{}-pub", prefix);
-    let pub_bytes = utils::read_from_file(&pubfile);
-    let decrypted_pub_bytes = decrypt(&pub_bytes, password).unwrap();
-    let pub_str = String::from_utf8(decrypted_pub_bytes).unwrap();
-    let obj = serde_json::from_str(&pub_str).unwrap();
-    let master_bytes = utils::read_from_file(&prefix);
-    (master_bytes, obj)
+pub const WRONG_PASSWORD: &str = "Wrong password";
+pub const CORRUPTED_DATA: &str = "Encrypted data is corrupted";
+
+// Error of a failed decryption, the versioned format tells a wrong password from a bad header
+pub fn decrypt_error(bytes: &[u8], e: Error) -> String {
+    match e {
+        Error::Unsupported(msg) if !is_legacy(bytes) => msg.to_string(),
+        _ => WRONG_PASSWORD.to_string(),
+    }
+}
+
+// Error of unreadable decrypted data: legacy files have no integrity tag, so a wrong password
+// shows up as garbage, while the versioned format was already authenticated by GCM
+pub fn content_error(bytes: &[u8]) -> String {
+    match is_legacy(bytes) {
+        true => WRONG_PASSWORD.to_string(),
+        false => CORRUPTED_DATA.to_string(),
+    }
+}
+
+fn decrypt_json<T: serde::de::DeserializeOwned>(bytes: &[u8], password: &str) -> Result<T, String> {
+    let decrypted = decrypt(bytes, password).map_err(|e| decrypt_error(bytes, e))?;
+    let serialized = String::from_utf8(decrypted).map_err(|_| content_error(bytes))?;
+    serde_json::from_str(&serialized).map_err(|_| content_error(bytes))
 }
 
-pub fn export_encrypted_keys(master: &MasterAccount, prefix: &str, password: &str) {
-    utils::write_to_file(&prefix, master.encrypted());
+pub fn import_encrypted_keys(prefix: &str, password: &str) -> Result<(Vec<u8>, ExtendedPubKey), String> {
     let pubfile = format!("{}-pub", prefix);
-    let pubk = master.master_public();
-    let serialized = serde_json::to_string(&pubk).unwrap();
-    let serial_bytes = serialized.into_bytes();
-    if let Ok(encrypted_bytes) = encrypt(serial_bytes.as_slice(), password) {
-        utils::write_to_file(&pubfile, &encrypted_bytes);
-    } else {
-        utils::fatal_kill("Failed to encrypt and save keys");
+    let pub_bytes = utils::read_from_file(&pubfile)?;
+    let obj = decrypt_json(&pub_bytes, password)?;
+    let mut master_bytes = utils::read_from_file(&prefix)?;
+    if !is_legacy(&master_bytes) {
+        master_bytes = decrypt(&master_bytes, password).map_err(|e| decrypt_error(&master_bytes, e))?;
+    }
+    Ok((master_bytes, obj))
+}
+
+pub fn import_encrypted_imported_keys(prefix: &str, password: &str) -> Result<Vec<ImportedKey>, String> {
+    let keysfile = format!("{}-keys", prefix);
+    if !utils::file_exists(&keysfile) {
+        return Ok(vec![]);
     }
+    let bytes = utils::read_from_file(&keysfile)?;
+    decrypt_json(&bytes, password)
 }
 
 pub fn encrypt_and_export(privkey: &ExtendedPrivKey, pubkey: &ExtendedPubKey, prefix: &str, password: &str) {
@@ -40,52 +64,114 @@ pub fn encrypt_and_export(privkey: &ExtendedPrivKey, pubkey: &ExtendedPubKey, pr
     let p_serial = serde_json::to_string(privkey).unwrap();
     let p_serial_bytes = p_serial.into_bytes();
     if let Ok(encrypted_bytes) = encrypt(p_serial_bytes.as_slice(), password) {
-        utils::write_to_file(prefix, &encrypted_bytes);
+        if let Err(e) = utils::write_to_file(prefix, &encrypted_bytes) {
+            utils::fatal_kill(&e);
+        }
     } else {
         utils::fatal_kill("Failed to encrypt and save keys");
     }
     let pub_serial = serde_json::to_string(pubkey).unwrap();
     let pub_serial_bytes = pub_serial.into_bytes();
     if let Ok(encrypted_bytes) = encrypt(pub_serial_bytes.as_slice(), password) {
-        utils::write_to_file(&pubfile, &encrypted_bytes);
+        if let Err(e) = utils::write_to_file(&pubfile, &encrypted_bytes) {
+            utils::fatal_kill(&e);
+        }
     } else {
         utils::fatal_kill("Failed to encrypt and save keys");
     }
 }
 
 /*
- *  As taken from rust-wallet::account::Seed
+ *  Versioned format: MAGIC | version | kdf | scrypt log_n | r | p | salt | nonce | tag | ciphertext
+ *  The header is authenticated as additional data of the AES-256-GCM cipher
  */
 
- pub fn encrypt(data: &[u8], passphrase: &str) -> Result<Vec<u8>, Error> {
-     let mut key = [0u8; 32];
-     let mut sha2 = Sha256::new();
-     sha2.input(passphrase.as_bytes());
-     sha2.result(&mut key);
+const MAGIC: &[u8; 4] = b"BCWE";
+const VERSION: u8 = 1;
+const KDF_SCRYPT: u8 = 1;
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const HEADER_LEN: usize = 4 + 1 + 1 + 1 + 4 + 4 + SALT_LEN + NONCE_LEN;
 
-     let mut encryptor = aes::ecb_encryptor(aes::KeySize::KeySize256, &key, blockmodes::PkcsPadding {});
-     let mut encrypted = Vec::new();
-     let mut reader = buffer::RefReadBuffer::new(data);
-     let mut buffer = [0u8; 1024];
-     let mut writer = buffer::RefWriteBuffer::new(&mut buffer);
-     loop {
-         let result = encryptor.encrypt(&mut reader, &mut writer, true)?;
-         encrypted.extend(
-             writer
-                .take_read_buffer()
-                .take_remaining()
-                .iter()
-                .map(|i| *i),
-         );
-         match result {
-             BufferResult::BufferUnderflow => break,
-             BufferResult::BufferOverflow => {}
-         }
-     }
-     Ok(encrypted)
- }
+pub fn is_legacy(data: &[u8]) -> bool {
+    data.len() < 4 || &data[0..4] != MAGIC
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], log_n: u8, r: u32, p: u32) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    let params = ScryptParams::new(log_n, r, p);
+    scrypt(passphrase.as_bytes(), salt, &params, &mut key);
+    key
+}
+
+pub fn encrypt(data: &[u8], passphrase: &str) -> Result<Vec<u8>, Error> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(MAGIC);
+    header.push(VERSION);
+    header.push(KDF_SCRYPT);
+    header.push(SCRYPT_LOG_N);
+    header.extend_from_slice(&SCRYPT_R.to_be_bytes());
+    header.extend_from_slice(&SCRYPT_P.to_be_bytes());
+    header.extend_from_slice(&salt);
+    header.extend_from_slice(&nonce);
+
+    let key = derive_key(passphrase, &salt, SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P);
+    let mut cipher = AesGcm::new(aes::KeySize::KeySize256, &key, &nonce, &header);
+    let mut encrypted = vec![0u8; data.len()];
+    let mut tag = [0u8; TAG_LEN];
+    cipher.encrypt(data, &mut encrypted, &mut tag);
+
+    let mut out = header;
+    out.extend_from_slice(&tag);
+    out.extend(encrypted);
+    Ok(out)
+}
+
+pub fn decrypt(encrypted: &[u8], passphrase: &str) -> Result<Vec<u8>, Error> {
+    if is_legacy(encrypted) {
+        return decrypt_legacy(encrypted, passphrase);
+    }
+    if encrypted.len() < HEADER_LEN + TAG_LEN || encrypted[4] != VERSION || encrypted[5] != KDF_SCRYPT {
+        return Err(Error::Unsupported("unknown encrypted file version"));
+    }
+    let log_n = encrypted[6];
+    let mut r = [0u8; 4];
+    let mut p = [0u8; 4];
+    r.copy_from_slice(&encrypted[7..11]);
+    p.copy_from_slice(&encrypted[11..15]);
+    // Only the parameters written by encrypt, others could panic or exhaust memory
+    if log_n != SCRYPT_LOG_N || u32::from_be_bytes(r) != SCRYPT_R || u32::from_be_bytes(p) != SCRYPT_P {
+        return Err(Error::Unsupported("unsupported scrypt parameters"));
+    }
+    let salt = &encrypted[15..15+SALT_LEN];
+    let nonce = &encrypted[15+SALT_LEN..HEADER_LEN];
+    let header = &encrypted[0..HEADER_LEN];
+    let tag = &encrypted[HEADER_LEN..HEADER_LEN+TAG_LEN];
+    let data = &encrypted[HEADER_LEN+TAG_LEN..];
+
+    let key = derive_key(passphrase, salt, log_n, u32::from_be_bytes(r), u32::from_be_bytes(p));
+    let mut cipher = AesGcm::new(aes::KeySize::KeySize256, &key, nonce, header);
+    let mut decrypted = vec![0u8; data.len()];
+    if !cipher.decrypt(data, &mut decrypted, tag) {
+        return Err(Error::Passphrase);
+    }
+    Ok(decrypted)
+}
+
+/*
+ *  Legacy format, as taken from rust-wallet::account::Seed
+ */
 
- pub fn decrypt(encrypted: &[u8], passphrase: &str) -> Result<Vec<u8>, Error> {
+ fn decrypt_legacy(encrypted: &[u8], passphrase: &str) -> Result<Vec<u8>, Error> {
      let mut key = [0u8; 32];
      let mut sha2 = Sha256::new();
      sha2.input(passphrase.as_bytes());
@@ -111,4 +197,64 @@ pub fn encrypt_and_export(privkey: &ExtendedPrivKey, pubkey: &ExtendedPubKey, pr
          }
      }
      Ok(decrypted)
- }
\ No newline at end of file
+ }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA: &[u8] = b"{\"seed\":\"00112233\"}";
+
+    #[test]
+    fn round_trip() {
+        let encrypted = encrypt(DATA, "password").unwrap();
+        assert!(!is_legacy(&encrypted));
+        assert_eq!(decrypt(&encrypted, "password").unwrap(), DATA);
+        assert!(matches!(decrypt(&encrypted, "wrong"), Err(Error::Passphrase)));
+    }
+
+    #[test]
+    fn tampered_data() {
+        let encrypted = encrypt(DATA, "password").unwrap();
+        // Nonce, part of the additional data only
+        let mut tampered = encrypted.clone();
+        tampered[HEADER_LEN - 1] ^= 1;
+        assert!(matches!(decrypt(&tampered, "password"), Err(Error::Passphrase)));
+        // Ciphertext and tag
+        for &i in &[HEADER_LEN, encrypted.len() - 1] {
+            let mut tampered = encrypted.clone();
+            tampered[i] ^= 1;
+            assert!(matches!(decrypt(&tampered, "password"), Err(Error::Passphrase)));
+        }
+        // Version and scrypt parameters are refused before deriving the key
+        for &i in &[4, 6, 10, 14] {
+            let mut tampered = encrypted.clone();
+            tampered[i] ^= 0x40;
+            assert!(matches!(decrypt(&tampered, "password"), Err(Error::Unsupported(_))));
+        }
+        assert!(matches!(decrypt(&encrypted[0..HEADER_LEN], "password"), Err(Error::Unsupported(_))));
+    }
+
+    #[test]
+    fn read_legacy() {
+        let mut key = [0u8; 32];
+        let mut sha2 = Sha256::new();
+        sha2.input(b"password");
+        sha2.result(&mut key);
+        let mut encrypted = Vec::new();
+        let mut reader = buffer::RefReadBuffer::new(DATA);
+        let mut buffer = [0u8; 1024];
+        let mut writer = buffer::RefWriteBuffer::new(&mut buffer);
+        let mut encryptor = aes::ecb_encryptor(aes::KeySize::KeySize256, &key, blockmodes::PkcsPadding {});
+        loop {
+            let result = encryptor.encrypt(&mut reader, &mut writer, true).unwrap();
+            encrypted.extend(writer.take_read_buffer().take_remaining().iter().map(|i| *i));
+            match result {
+                BufferResult::BufferUnderflow => break,
+                BufferResult::BufferOverflow => {}
+            }
+        }
+        assert!(is_legacy(&encrypted));
+        assert_eq!(decrypt(&encrypted, "password").unwrap(), DATA);
+    }
+}
@@ -4,8 +4,8 @@ use std::{
         stdout,
         Write,
     },
-    process,
 };
+use rand::Rng;
 use termion::input::TermRead;
 use bitcoin_wallet::mnemonic::Mnemonic;
 use bitcoincore_rpc::{
@@ -17,7 +17,13 @@ use qrcode::{
     render::unicode,  
 };
 
-pub fn show_new_mnemonic_from_words(mnemonic: &Vec<&str>) {
+use crate::utils;
+
+// Attempts to enter each word of the backup check
+const BACKUP_CHECK_ATTEMPTS: u32 = 3;
+
+// The backup is checked with a quiz unless skipped
+pub fn show_new_mnemonic_from_words(mnemonic: &Vec<&str>, check: bool) {
     let stdout = stdout();
     let mut stdout = stdout.lock();
     let stdin = stdin();
@@ -36,17 +42,54 @@ pub fn show_new_mnemonic_from_words(mnemonic: &Vec<&str>) {
             stdout.write_all(b"\r").unwrap();
             stdout.flush().unwrap();
         } else {
-            stdout.write_all(b"\nFailed to read input! Discard your words and try again\n").unwrap();
-            process::exit(1);
+            stdout.write_all(b"\n").unwrap();
+            drop(stdout);
+            utils::fatal_kill("Failed to read input! Discard your words and try again");
         }
     }
     stdout.write_all(b"                                                                     \n").unwrap();
+    drop(stdout);
+    drop(stdin);
+    if check {
+        check_backup(mnemonic);
+    }
 }
 
-pub fn show_new_mnemonic(mnemonic: &Mnemonic) {
+pub fn show_new_mnemonic(mnemonic: &Mnemonic, check: bool) {
     let mnemonic_str = mnemonic.to_string();
     let words: Vec<&str> = mnemonic_str.split(" ").collect();
-    show_new_mnemonic_from_words(&words);
+    show_new_mnemonic_from_words(&words, check);
+}
+
+// Asks for random word positions, each answer must match the word shown within the attempts
+pub fn check_backup(mnemonic: &Vec<&str>) {
+    let checks = std::cmp::min(3, mnemonic.len());
+    let mut positions: Vec<usize> = vec![];
+    while positions.len() < checks {
+        let p = rand::thread_rng().gen_range(0..mnemonic.len());
+        if !positions.contains(&p) {
+            positions.push(p);
+        }
+    }
+    positions.sort();
+    println!("Check your backup, enter the requested words as you wrote them down:");
+    for p in positions {
+        let mut attempt = 1;
+        loop {
+            let (word, success) = get_secret(&format!("Word #{}: ", p+1), None);
+            if !success {
+                utils::fatal_kill("Failed to get words!");
+            }
+            if word.trim().to_lowercase() == mnemonic[p] {
+                break;
+            }
+            if attempt >= BACKUP_CHECK_ATTEMPTS {
+                utils::fatal_kill("Backup check failed, discard your words and try again");
+            }
+            println!("Word #{} does not match, check your backup and try again ({}/{})", p+1, attempt, BACKUP_CHECK_ATTEMPTS);
+            attempt += 1;
+        }
+    }
 }
 
 pub fn get_secret(prompt: &str, confirm: Option<&str>) -> (String, bool) {
@@ -89,6 +132,20 @@ pub fn get_secret(prompt: &str, confirm: Option<&str>) -> (String, bool) {
     ("".to_string(), false)
 }
 
+pub fn confirm(prompt: &str) -> bool {
+    let stdout = stdout();
+    let mut stdout = stdout.lock();
+    let stdin = stdin();
+    let mut stdin = stdin.lock();
+
+    stdout.write_all(format!("{} [y/N]: ", prompt).as_bytes()).unwrap();
+    stdout.flush().unwrap();
+    match stdin.read_line() {
+        Ok(Some(answer)) => answer.trim().to_lowercase() == "y" || answer.trim().to_lowercase() == "yes",
+        _ => false,
+    }
+}
+
 pub fn show_coins(coins: &Vec<ListUnspentResultEntry>, total: Amount, as_sats: bool) {
     let den = match as_sats {
         true => "sats",
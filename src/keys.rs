@@ -0,0 +1,53 @@
+use bitcoin::{
+    Address, PrivateKey,
+    network::constants::Network,
+    secp256k1::Secp256k1,
+};
+use bitcoin_wallet::account::AccountAddressType;
+use serde::{Deserialize, Serialize};
+
+use crate::master;
+
+// Standalone private key stored next to the master, e.g. from a paper wallet
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ImportedKey {
+    wif: String,
+    purpose: u32,
+}
+
+impl ImportedKey {
+    pub fn from_wif(
+        wif: &str,
+        address_type: AccountAddressType,
+        network: Network,
+    ) -> Result<ImportedKey, String> {
+        let privkey = match PrivateKey::from_wif(wif.trim()) {
+            Ok(p) => p,
+            Err(_) => return Err("Invalid WIF private key".to_string()),
+        };
+        if (privkey.network == Network::Bitcoin) != (network == Network::Bitcoin) {
+            return Err("Private key does not belong to the selected network".to_string());
+        }
+        if !privkey.compressed && address_type != AccountAddressType::P2PKH {
+            return Err("Uncompressed private keys can only be used with P2PKH addresses".to_string());
+        }
+        Ok(ImportedKey {
+            wif: privkey.to_wif(),
+            purpose: address_type.as_u32(),
+        })
+    }
+
+    pub fn private_key(&self) -> PrivateKey {
+        PrivateKey::from_wif(&self.wif).unwrap()
+    }
+
+    pub fn address_type(&self) -> AccountAddressType {
+        AccountAddressType::from_u32(self.purpose)
+    }
+
+    pub fn address(&self, network: Network) -> Address {
+        let secp = Secp256k1::signing_only();
+        let pk = self.private_key().public_key(&secp);
+        master::to_address(self.address_type(), &pk, network)
+    }
+}
@@ -11,18 +11,24 @@ use bitcoin_wallet::{
         MasterKeyEntropy,
     },
 };
-use bitcoin::{Address, network::constants::Network};
+use bitcoin::{Address, PublicKey, network::constants::Network, secp256k1::Secp256k1, util::bip32::DerivationPath};
 use bitcoincore_rpc::{
     bitcoin::{self as bcore},
 };
-use master::Master;
+use keys::ImportedKey;
+use master::{Master, WatchOnly};
 
+mod bip85;
 mod cli;
 mod core;
 mod crypto;
 mod io;
+mod keys;
 mod master;
+mod paper;
 mod utils;
+mod wallet;
+mod wordlist;
 
 fn main() {
     let opts: cli::Opts = cli::Opts::parse();
@@ -46,32 +52,128 @@ fn main() {
 
     match &opts.subcommand {
         cli::SubCommand::Address(cmd_opts) => {
-            let (master_acc, password) = init_master(&opts, network);
-            let address_type: AccountAddressType = parse_address_type(cmd_opts.address_type.clone());
-
             let n = cmd_opts.account_number;
             let m = cmd_opts.subaccount;
             let k = cmd_opts.kix;
             match &cmd_opts.subcommand {
-                cli::AddressSubCommand::Generate(_) => {
-                    let pk = master_acc.get_child_pk(password.clone(), address_type, n, m, k).unwrap();
-                    let addr = match address_type {
-                        AccountAddressType::P2PKH => Address::p2pkh(&pk, network),
-                        AccountAddressType::P2SHWPKH => Address::p2shwpkh(&pk, network),
-                        AccountAddressType::P2WPKH | _ => Address::p2wpkh(&pk, network),
-                    };
+                cli::AddressSubCommand::Generate(sub_opts) => {
+                    let (pk, address_type, origin, master_acc) = derive_child_pk(&opts, network, cmd_opts.address_type.clone(), cmd_opts.path.as_ref(), n, m, k);
+                    let addr = master::to_address(address_type, &pk, network);
+                    // Usage is recorded before the address is shown, it cannot fail afterwards
+                    let mut updated: Option<(Master, String, String)> = None;
+                    let mut old_format = false;
+                    if let (Some((mut master_acc, password)), Some(_)) = (master_acc, &opts.label) {
+                        let prefix = wallet_prefix(&opts, label);
+                        // Loose files of older versions are only converted by master migrate
+                        if wallet::exists(&prefix) {
+                            if cmd_opts.path == None {
+                                master_acc.record_usage(password.clone(), address_type, n, m, k, opts.legacy_paths).unwrap_or_else(|e| utils::fatal_kill(&e));
+                            }
+                            if let Some(note) = &sub_opts.note {
+                                master_acc.set_address_label(addr.to_string(), note.clone());
+                            }
+                            updated = Some((master_acc, password, prefix));
+                        } else {
+                            old_format = true;
+                        }
+                    }
                     println!("{}{}Address:{}{} {} {}         ",
                         style::Bold, color::Fg(color::Blue), style::Reset, color::Fg(color::Blue),
                         addr, style::Reset
                     );
+                    if let Some(origin) = origin {
+                        println!("{}Path:{} {}", style::Bold, style::Reset, origin);
+                    }
                     io::show_qr(addr.to_string());
-                }
+                    if old_format {
+                        utils::warn(&format!("Wallet {} uses the old file format, run `master migrate` to record used addresses and notes", label));
+                    }
+                    if let Some((master_acc, password, prefix)) = updated {
+                        master_acc.save(password, &prefix);
+                    }
+                },
+                cli::AddressSubCommand::Privkey(sub_opts) => {
+                    utils::warn("Anyone with this private key can spend the coins of its address. Never share it with untrusted parties.");
+                    if !io::confirm("Show the private key?") || !io::confirm("Are you sure? It will be displayed in plain text") {
+                        utils::fatal_kill("Aborted");
+                    }
+                    let (master_acc, password) = init_master(&opts, network);
+                    let (path, address_type) = match &cmd_opts.path {
+                        Some(p) => parse_path(p, cmd_opts.address_type.clone()),
+                        None => {
+                            let address_type = parse_address_type(cmd_opts.address_type.clone());
+                            let path = master_acc.child_path(&password, address_type, n, m, k, opts.legacy_paths).unwrap_or_else(|e| utils::fatal_kill(&e));
+                            (path, address_type)
+                        },
+                    };
+                    let privkey = master_acc.get_child_privkey(password, &path).unwrap_or_else(|e| utils::fatal_kill(&e.to_string()));
+                    let secp = Secp256k1::new();
+                    let addr = master::to_address(address_type, &privkey.public_key(&secp), network);
+                    println!("{}Address:{} {}", style::Bold, style::Reset, addr);
+                    println!("{}Path:{} {}", style::Bold, style::Reset, master::key_origin(master_acc.fingerprint(), &path));
+                    println!("{}{}WIF:{}{} {} {}", style::Bold, color::Fg(color::Red), style::Reset, color::Fg(color::Red), privkey.to_wif(), style::Reset);
+                    if sub_opts.qr {
+                        io::show_qr(privkey.to_wif());
+                    }
+                },
+                cli::AddressSubCommand::Find(sub_opts) => {
+                    let addr = Address::from_str(&sub_opts.address).unwrap_or_else(|e| utils::fatal_kill(&e.to_string()));
+                    // Testnet and regtest addresses share their prefixes, except for bech32
+                    if (addr.network == Network::Bitcoin) != (network == Network::Bitcoin) {
+                        utils::fatal_kill(&format!("Address {} belongs to {} but {} was requested", addr, addr.network, network));
+                    }
+                    let address_types = match &cmd_opts.address_type {
+                        Some(_) => vec![parse_address_type(cmd_opts.address_type.clone())],
+                        None => vec![AccountAddressType::P2PKH, AccountAddressType::P2SHWPKH, AccountAddressType::P2WPKH],
+                    };
+                    let found = if let Some(key) = &opts.xpub {
+                        let watch_only = WatchOnly::from_str(key, network).unwrap_or_else(|e| utils::fatal_kill(&e));
+                        let address_types = match (&cmd_opts.address_type, watch_only.address_type()) {
+                            (None, Some(a)) => vec![a],
+                            _ => address_types,
+                        };
+                        match watch_only.find_address(&addr, &address_types, sub_opts.max_index) {
+                            Ok(Some((m, k, address_type))) => Some((watch_only.child_origin(m, k), Some((m, k)), address_type)),
+                            Ok(None) => None,
+                            Err(e) => utils::fatal_kill(&e),
+                        }
+                    } else {
+                        let (master_acc, password) = init_master(&opts, network);
+                        match master_acc.find_address(password, &addr, &address_types, sub_opts.max_account, sub_opts.max_index, opts.legacy_paths) {
+                            Ok(Some((path, address_type))) => Some((Some(master::key_origin(master_acc.fingerprint(), &path)), None, address_type)),
+                            Ok(None) => None,
+                            Err(e) => utils::fatal_kill(&e.to_string()),
+                        }
+                    };
+                    match found {
+                        Some((origin, position, address_type)) => {
+                            println!("{}{}Found:{}{} {} {}",
+                                style::Bold, color::Fg(color::Green), style::Reset, color::Fg(color::Green),
+                                addr, style::Reset
+                            );
+                            match (origin, position) {
+                                (Some(origin), _) => println!("{}Path:{} {}", style::Bold, style::Reset, origin),
+                                // Without a key origin only the position below the account key is known
+                                (None, Some((m, k))) => println!("{}Chain:{} {} {}Index:{} {}", style::Bold, style::Reset, m, style::Bold, style::Reset, k),
+                                (None, None) => {},
+                            }
+                            println!("{}Type:{} {:?}", style::Bold, style::Reset, address_type);
+                        },
+                        None => match &opts.xpub {
+                            Some(_) => println!("Address {} not found below this account key (searched up to index {})", addr, sub_opts.max_index),
+                            None => println!("Address {} not found in this wallet (searched up to account {} and index {})", addr, sub_opts.max_account, sub_opts.max_index),
+                        },
+                    }
+                },
             }
         },
         cli::SubCommand::Master(cmd_opts) => {
             match &cmd_opts.subcommand {
                 cli::MasterSubCommand::New(sub_opts) => {
-                    let master_acc: Master;
+                    if opts.export && wallet::files(&wallet_prefix(&opts, label)).len() > 0 {
+                        utils::fatal_kill(&format!("Wallet {} already exists, choose another label", label));
+                    }
+                    let mut master_acc: Master;
                     let (password, success) = io::get_secret(
                         "Enter your password: ",
                         Some("Confirm your password: ")
@@ -85,16 +187,21 @@ fn main() {
                         3 | _   => MasterKeyEntropy::Paranoid,
                     };
                     if sub_opts.shamir_sharing {
-                        master_acc = Master::new_with_ss(password.clone(), entropy, network, sub_opts.min, sub_opts.max).unwrap();
+                        let (group_threshold, groups) = get_share_groups(&sub_opts.groups, sub_opts.min, sub_opts.max);
+                        master_acc = Master::new_with_ss(password.clone(), entropy, network, group_threshold, &groups, get_user_entropy(sub_opts, entropy as usize * 8), get_passphrase(&opts, true), !opts.skip_backup_check).unwrap_or_else(|e| utils::fatal_kill(&e));
                     } else {
-                        master_acc = Master::new(password.clone(), entropy, network).unwrap();
+                        master_acc = Master::new(password.clone(), entropy, network, get_passphrase(&opts, false), get_user_entropy(sub_opts, entropy as usize * 8), !opts.skip_backup_check).unwrap_or_else(|e| utils::fatal_kill(&e));
                     }
+                    master_acc.set_birthday(sub_opts.birthday);
                     if opts.export {
-                        master_acc.export_master(password.clone(), label);
+                        master_acc.export_master(password.clone(), &wallet_prefix(&opts, label));
                     }
                 },
-                cli::MasterSubCommand::Recover(_) => {
-                    let master_acc: Master;
+                cli::MasterSubCommand::Recover(sub_opts) => {
+                    if opts.export && wallet::files(&wallet_prefix(&opts, label)).len() > 0 {
+                        utils::fatal_kill(&format!("Wallet {} already exists, choose another label", label));
+                    }
+                    let mut master_acc: Master;
                     let (password, success) = io::get_secret(
                         "Enter your password: ",
                         Some("Confirm your password: ")
@@ -103,12 +210,13 @@ fn main() {
                         utils::fatal_kill("Failed to get password!");
                     }
                     if let Some(words) = opts.with_mnemonic {
-                        master_acc = Master::new_from_inline_mnemonic(words.clone(), password.clone(), network).unwrap();
+                        master_acc = Master::new_from_inline_mnemonic(words.clone(), password.clone(), network, get_passphrase(&opts, false)).unwrap_or_else(|e| utils::fatal_kill(&e));
                     } else {
-                        master_acc = Master::new_from_mnemonic(password.clone(), network, opts.shamir_shares).unwrap();
+                        master_acc = Master::new_from_mnemonic(password.clone(), network, opts.shamir_shares, get_passphrase(&opts, opts.shamir_shares.is_some())).unwrap_or_else(|e| utils::fatal_kill(&e));
                     }
+                    master_acc.set_birthday(sub_opts.birthday);
                     if opts.export {
-                        master_acc.export_master(password.clone(), label);
+                        master_acc.export_master(password.clone(), &wallet_prefix(&opts, label));
                     }
                 },
                 cli::MasterSubCommand::Pubkey(_) => {
@@ -116,6 +224,116 @@ fn main() {
                     let mpk = master_acc.get_master_public();
                     println!("{}", mpk);
                     io::show_qr(format!("{}", mpk).to_string());
+                },
+                cli::MasterSubCommand::Migrate(_) => {
+                    let prefix = wallet_prefix(&opts, label);
+                    if wallet::exists(&prefix) {
+                        println!("[+] Wallet {} already uses the current format", wallet::filename(&prefix));
+                        return;
+                    }
+                    let (master_acc, password) = init_master(&opts, network);
+                    master_acc.export_master(password, &prefix);
+                    println!("[+] Wallet migrated to {}, you can remove the old {} and {}-pub files once verified", wallet::filename(&prefix), prefix, prefix);
+                },
+                cli::MasterSubCommand::Passwd(_) => {
+                    let prefix = wallet_prefix(&opts, label);
+                    let (mut master_acc, password) = init_master(&opts, network);
+                    let (new_password, success) = io::get_secret(
+                        "Enter your new password: ",
+                        Some("Confirm your new password: ")
+                    );
+                    if !success {
+                        utils::fatal_kill("Failed to get password!");
+                    }
+                    if let Err(e) = master_acc.change_password(&password, &new_password) {
+                        utils::fatal_kill(&e.to_string());
+                    }
+                    master_acc.save(new_password, &prefix);
+                    // Older files and backups would still open with the old password
+                    let removed = wallet::delete_old_versions(&prefix).unwrap_or_else(|e| utils::fatal_kill(&e));
+                    for f in removed {
+                        println!("[+] Removed {}", f);
+                    }
+                    println!("[+] Password changed for {}", wallet::filename(&prefix));
+                },
+                cli::MasterSubCommand::Split(sub_opts) => {
+                    let (group_threshold, groups) = get_share_groups(&sub_opts.groups, sub_opts.min, sub_opts.max);
+                    let (master_acc, password) = init_master(&opts, network);
+                    println!("{}Fingerprint:{} {}", style::Bold, style::Reset, master_acc.fingerprint());
+                    master_acc.split(&password, group_threshold, &groups, get_passphrase(&opts, true), !opts.skip_backup_check).unwrap_or_else(|e| utils::fatal_kill(&e));
+                },
+                cli::MasterSubCommand::VerifyShares(_) => {
+                    // The wallet is unlocked first to fail early on a wrong password
+                    let wallet_fingerprint = match opts.label {
+                        Some(_) => Some(init_master(&opts, network).0.fingerprint()),
+                        None => None,
+                    };
+                    let fingerprint = master::verify_shares(network, get_passphrase(&opts, true)).unwrap_or_else(|e| utils::fatal_kill(&e));
+                    println!("{}Fingerprint:{} {}", style::Bold, style::Reset, fingerprint);
+                    if let Some(f) = wallet_fingerprint {
+                        if f != fingerprint {
+                            utils::fatal_kill(&format!("Shares do not match wallet {} ({})", label, f));
+                        }
+                        println!("{}[+] Shares match wallet {}{}", color::Fg(color::Green), label, style::Reset);
+                    }
+                },
+                cli::MasterSubCommand::Bip85(sub_opts) => {
+                    let app = bip85::App::from_args(&sub_opts.app, sub_opts.words, sub_opts.bytes).unwrap_or_else(|e| utils::fatal_kill(&e));
+                    let (master_acc, password) = init_master(&opts, network);
+                    let path = app.path(sub_opts.index);
+                    let entropy = master_acc.bip85_entropy(password, &path).unwrap_or_else(|e| utils::fatal_kill(&e));
+                    println!("{}Path:{} {}", style::Bold, style::Reset, master::key_origin(master_acc.fingerprint(), &path));
+                    let child = match app {
+                        bip85::App::Bip39 { words } => {
+                            let mnemonic = bip85::to_mnemonic(&entropy, words).unwrap_or_else(|e| utils::fatal_kill(&e));
+                            io::show_new_mnemonic(&mnemonic, !opts.skip_backup_check);
+                            println!("{}[DONE]{}                                                          ", color::Fg(color::Green), style::Reset);
+                            return;
+                        },
+                        bip85::App::Wif => bip85::to_wif(&entropy, master_acc.network()),
+                        bip85::App::Xprv => bip85::to_xprv(&entropy, master_acc.network()),
+                        bip85::App::Hex { bytes } => Ok(bip85::to_hex(&entropy, bytes)),
+                    };
+                    match child {
+                        Ok(c) => println!("{}{}Child:{}{} {} {}", style::Bold, color::Fg(color::Red), style::Reset, color::Fg(color::Red), c, style::Reset),
+                        Err(e) => utils::fatal_kill(&e),
+                    }
+                },
+                cli::MasterSubCommand::Info(sub_opts) => {
+                    let (master_acc, password) = init_master(&opts, network);
+                    let fingerprint = master_acc.fingerprint();
+                    // Account keys are derived first, so --legacy-paths errors come before any output
+                    let address_types = [AccountAddressType::P2PKH, AccountAddressType::P2SHWPKH, AccountAddressType::P2WPKH];
+                    let accounts: Vec<Vec<String>> = (0..sub_opts.accounts).map(|n| {
+                        address_types.iter().map(|&address_type| {
+                            let path = master_acc.account_path(&password, address_type, n, opts.legacy_paths).unwrap_or_else(|e| utils::fatal_kill(&e));
+                            let xpub = master_acc.get_account_public(password.clone(), &path).unwrap();
+                            let encoded = master::encode_xpub(&xpub, master::default_encoding(address_type, network)).unwrap();
+                            format!("{:?}:\t{}{}", address_type, master::key_origin(fingerprint, &path), encoded)
+                        }).collect()
+                    }).collect();
+                    println!("{}Fingerprint:{} {}", style::Bold, style::Reset, fingerprint);
+                    println!("{}Network:{} {}", style::Bold, style::Reset, master_acc.network());
+                    println!("{}Master:{} {}{}", style::Bold, style::Reset,
+                        master::key_origin(fingerprint, &DerivationPath::from(vec![])), master_acc.get_master_public()
+                    );
+                    if let Some(birthday) = master_acc.state().birthday {
+                        println!("{}Birthday:{} {}", style::Bold, style::Reset, birthday);
+                    }
+                    for acc in &master_acc.state().accounts {
+                        println!("{}In use:{} {:?} account {} (next receive {}, next change {})", style::Bold, style::Reset,
+                            AccountAddressType::from_u32(acc.purpose), acc.account, acc.next_receive, acc.next_change
+                        );
+                    }
+                    for desc in &master_acc.state().descriptors {
+                        println!("{}Descriptor:{} {}", style::Bold, style::Reset, desc);
+                    }
+                    for (n, lines) in accounts.iter().enumerate() {
+                        println!("{}{}Account #{}{}", style::Bold, color::Fg(color::Blue), n, style::Reset);
+                        for line in lines {
+                            println!("{}", line);
+                        }
+                    }
                 }
             }
         },
@@ -124,11 +342,20 @@ fn main() {
                 cli::GetSubCommand::Coins(sub_opts) => {
 
                     let mut node = core::Node::from_connection_string(cmd_opts.rpc.clone()).unwrap();
-                    node.connect(network.to_string()).unwrap();
+                    let chain = match network {
+                        Network::Bitcoin => "main",
+                        Network::Testnet => "test",
+                        Network::Regtest => "regtest",
+                    };
+                    if let Err(e) = node.connect(chain) {
+                        utils::fatal_kill(&e);
+                    }
 
                     // let mut coins: Vec<ListUnspentResultEntry>;
                     // let mut total: bcore::Amount;
                     let address: bcore::Address;
+                    let mut imported_addresses: Vec<bcore::Address> = vec![];
+                    let mut birthday: Option<u64> = None;
 
                     let _network = match network {
                         Network::Bitcoin => bcore::Network::Bitcoin,
@@ -141,7 +368,6 @@ fn main() {
                         address = bcore::Address::from_str(addr).unwrap();
                         node.import(label, Some(address.clone()), None).unwrap();
                     } else {
-                        let (master_acc, password) = init_master(&opts, network);
                         let n = match sub_opts.account_number {
                             Some(a) => a,
                             None => 0,
@@ -155,9 +381,7 @@ fn main() {
                             None => 0,
                         };
 
-                        let address_type: AccountAddressType = parse_address_type(sub_opts.address_type.clone());
-                        
-                        let _pk = master_acc.get_child_pk(password.clone(), address_type, n, sub, kix).unwrap();
+                        let (_pk, address_type, _, master_acc) = derive_child_pk(&opts, network, sub_opts.address_type.clone(), sub_opts.path.as_ref(), n, sub, kix);
                         let pk = bcore::PublicKey::from_str(&_pk.to_string()).unwrap();
                         address = match address_type {
                             AccountAddressType::P2PKH => bcore::Address::p2pkh(&pk, _network),
@@ -166,16 +390,34 @@ fn main() {
                         };
                         node.load_watchonly_wallet(label);
                         node.import(label, None, Some(&pk)).unwrap();
+
+                        if let Some((master_acc, _)) = master_acc {
+                            birthday = master_acc.state().birthday;
+                            for key in master_acc.imported_keys() {
+                                let imported = bcore::Address::from_str(&key.address(network).to_string()).unwrap();
+                                node.import(label, Some(imported.clone()), None).unwrap();
+                                imported_addresses.push(imported);
+                            }
+                        }
                     }
 
                     if sub_opts.rescan {
-                        if let Err(e) = node.rescan(sub_opts.last, sub_opts.start_block, sub_opts.end_block) {
+                        let mut start_block = sub_opts.start_block;
+                        if start_block == None && sub_opts.last == None {
+                            if let Some(birthday) = birthday {
+                                println!("[+] Rescanning from wallet birthday at height {}", birthday);
+                                start_block = Some(birthday as usize);
+                            }
+                        }
+                        if let Err(e) = node.rescan(sub_opts.last, start_block, sub_opts.end_block) {
                             node.unload(Some(label));
                             utils::fatal_kill(&e);
                         }
                     }
 
-                    match node.get_coins(sub_opts.limit, sub_opts.amount.clone(), Some(&vec![&address]), sub_opts.desc) {
+                    let mut addresses = vec![&address];
+                    addresses.extend(imported_addresses.iter());
+                    match node.get_coins(sub_opts.limit, sub_opts.amount.clone(), Some(&addresses), sub_opts.desc) {
                         Ok((coins, total)) => io::show_coins(&coins, total, sub_opts.sats),
                         Err(_) => println!("Failed to fetch coins"),
                     };
@@ -184,6 +426,148 @@ fn main() {
                 }
             }
         },
+        cli::SubCommand::Account(cmd_opts) => {
+            match &cmd_opts.subcommand {
+                cli::AccountSubCommand::Xpub(sub_opts) => {
+                    let (master_acc, password) = init_master(&opts, network);
+                    let (path, address_type) = match &sub_opts.path {
+                        Some(p) => parse_path(p, sub_opts.address_type.clone()),
+                        None => {
+                            let address_type: AccountAddressType = parse_address_type(sub_opts.address_type.clone());
+                            let path = master_acc.account_path(&password, address_type, sub_opts.account_number, opts.legacy_paths).unwrap_or_else(|e| utils::fatal_kill(&e));
+                            (path, address_type)
+                        },
+                    };
+                    let xpub = master_acc.get_account_public(password.clone(), &path).unwrap();
+                    let encoding = match &sub_opts.encoding {
+                        Some(e) => e.as_str(),
+                        None => master::default_encoding(address_type, network),
+                    };
+                    let encoded = match master::encode_xpub(&xpub, encoding) {
+                        Ok(e) => e,
+                        Err(e) => utils::fatal_kill(&e),
+                    };
+                    let origin = master::key_origin(master_acc.fingerprint(), &path);
+                    println!("{}{}", origin, encoded);
+                    io::show_qr(format!("{}{}", origin, encoded));
+                }
+            }
+        },
+        cli::SubCommand::Keys(cmd_opts) => {
+            match &cmd_opts.subcommand {
+                cli::KeysSubCommand::Import(sub_opts) => {
+                    let (wif, success) = io::get_secret("Enter your WIF private key: ", None);
+                    if !success {
+                        utils::fatal_kill("Failed to get private key!");
+                    }
+                    let address_type = parse_address_type(sub_opts.address_type.clone());
+                    let key = ImportedKey::from_wif(&wif, address_type, network).unwrap_or_else(|e| utils::fatal_kill(&e));
+                    let (mut master_acc, password) = init_master(&opts, network);
+                    let addr = key.address(network);
+                    if master_acc.imported_keys().iter().any(|k| k.address(network) == addr) {
+                        utils::fatal_kill("Private key already imported");
+                    }
+                    master_acc.import_key(key);
+                    master_acc.save(password, &wallet_prefix(&opts, label));
+                    println!("{}Imported:{} {}", style::Bold, style::Reset, addr);
+                },
+            }
+        },
+        cli::SubCommand::Wallets(cmd_opts) => {
+            let datadir = wallet::datadir(opts.datadir.as_ref()).unwrap_or_else(|e| utils::fatal_kill(&e));
+            match &cmd_opts.subcommand {
+                cli::WalletsSubCommand::List(_) => {
+                    let labels = wallet::list(&datadir).unwrap_or_else(|e| utils::fatal_kill(&e));
+                    println!("{}Wallets in {}:{}", style::Bold, datadir.display(), style::Reset);
+                    for l in labels {
+                        println!("{}", l);
+                    }
+                },
+                cli::WalletsSubCommand::Info(sub_opts) => {
+                    let p = wallet::prefix(&datadir, &sub_opts.label).unwrap_or_else(|e| utils::fatal_kill(&e));
+                    if !wallet::exists(&p) {
+                        utils::fatal_kill(&format!("Wallet {} not found or in the older format, run master migrate first", sub_opts.label));
+                    }
+                    let (version, network) = wallet::read_header(&p).unwrap_or_else(|e| utils::fatal_kill(&e));
+                    println!("{}File:{} {}", style::Bold, style::Reset, wallet::filename(&p));
+                    println!("{}Version:{} {}", style::Bold, style::Reset, version);
+                    println!("{}Network:{} {}", style::Bold, style::Reset, network);
+                },
+                cli::WalletsSubCommand::Rename(sub_opts) => {
+                    let from = wallet::prefix(&datadir, &sub_opts.from).unwrap_or_else(|e| utils::fatal_kill(&e));
+                    let to = wallet::prefix(&datadir, &sub_opts.to).unwrap_or_else(|e| utils::fatal_kill(&e));
+                    wallet::rename(&from, &to).unwrap_or_else(|e| utils::fatal_kill(&e));
+                    println!("[+] Wallet {} renamed to {}", sub_opts.from, sub_opts.to);
+                },
+                cli::WalletsSubCommand::Delete(sub_opts) => {
+                    let p = wallet::prefix(&datadir, &sub_opts.label).unwrap_or_else(|e| utils::fatal_kill(&e));
+                    if wallet::files(&p).len() == 0 {
+                        utils::fatal_kill(&format!("Wallet {} not found", sub_opts.label));
+                    }
+                    utils::warn("Deleting a wallet cannot be undone, make sure you have a backup of its seed.");
+                    if !io::confirm(&format!("Delete wallet {}?", sub_opts.label)) || !io::confirm("Are you sure?") {
+                        utils::fatal_kill("Aborted");
+                    }
+                    wallet::delete(&p).unwrap_or_else(|e| utils::fatal_kill(&e));
+                    println!("[+] Wallet {} deleted", sub_opts.label);
+                },
+            }
+        },
+        cli::SubCommand::Backup(cmd_opts) => {
+            match &cmd_opts.subcommand {
+                cli::BackupSubCommand::Paper(sub_opts) => {
+                    let format = paper::Format::from_args(sub_opts.format.as_ref(), &sub_opts.out).unwrap_or_else(|e| utils::fatal_kill(&e));
+                    let (master_acc, password) = init_master(&opts, network);
+                    // The wallet file only keeps the seed, the words are entered again and checked
+                    let words = match &opts.with_mnemonic {
+                        Some(w) => w.clone(),
+                        None => {
+                            let (w, success) = io::get_secret("Enter the mnemonic or share words to print: ", None);
+                            if !success {
+                                utils::fatal_kill("Failed to get words!");
+                            }
+                            w
+                        },
+                    };
+                    let words = words.to_lowercase();
+                    let words: Vec<&str> = words.split_whitespace().collect();
+                    // Share lengths depend on the secret size, a valid share checksum tells them apart
+                    let title = match master::describe_share(&words) {
+                        Ok(title) => {
+                            utils::warn("A single share cannot be checked against the wallet, use master verify-shares to audit the set.");
+                            title
+                        },
+                        Err(_) => {
+                            let fingerprint = master::mnemonic_fingerprint(&words, network, get_passphrase(&opts, false)).unwrap_or_else(|e| utils::fatal_kill(&e));
+                            if fingerprint != master_acc.fingerprint() {
+                                utils::fatal_kill(&format!("Words do not match wallet {} ({})", label, master_acc.fingerprint()));
+                            }
+                            format!("BIP39 mnemonic, {} words", words.len())
+                        },
+                    };
+                    let address_type = parse_address_type(sub_opts.address_type.clone());
+                    let path = master_acc.account_path(&password, address_type, sub_opts.account_number, opts.legacy_paths).unwrap_or_else(|e| utils::fatal_kill(&e));
+                    let xpub = master_acc.get_account_public(password, &path).unwrap();
+                    let origin = master::key_origin(master_acc.fingerprint(), &path);
+                    let sheet = paper::Sheet {
+                        title,
+                        words: words.iter().map(|w| w.to_string()).collect(),
+                        fingerprint: master_acc.fingerprint(),
+                        descriptors: vec![
+                            master::descriptor(address_type, &origin, &xpub, 0),
+                            master::descriptor(address_type, &origin, &xpub, 1),
+                        ],
+                        network,
+                        birthday: master_acc.state().birthday,
+                        printed: paper::today(),
+                    };
+                    let bytes = paper::render(&sheet, format).unwrap_or_else(|e| utils::fatal_kill(&e));
+                    utils::create_private_file(&sub_opts.out, &bytes).unwrap_or_else(|e| utils::fatal_kill(&e));
+                    utils::warn("The sheet holds your seed words, print it offline and delete the file afterwards.");
+                    println!("[+] Paper backup written to {}", sub_opts.out);
+                },
+            }
+        },
     }
 }
 
@@ -201,7 +585,173 @@ fn parse_address_type(addr: Option<String>) -> AccountAddressType {
     }
 }
 
+fn parse_path(path: &str, address_type: Option<String>) -> (DerivationPath, AccountAddressType) {
+    let address_type = match address_type {
+        Some(_) => Some(parse_address_type(address_type)),
+        None => None,
+    };
+    master::parse_path(path, address_type).unwrap_or_else(|e| utils::fatal_kill(&e))
+}
+
+// Child public key, its script type and key origin, from the watch-only xpub if given.
+// The master and its password are returned when one had to be unlocked
+fn derive_child_pk(
+    opts: &cli::Opts,
+    network: Network,
+    address_type: Option<String>,
+    path: Option<&String>,
+    n: u32,
+    m: u32,
+    k: u32,
+) -> (PublicKey, AccountAddressType, Option<String>, Option<(Master, String)>) {
+    if let Some(key) = &opts.xpub {
+        let watch_only = WatchOnly::from_str(key, network).unwrap_or_else(|e| utils::fatal_kill(&e));
+        let address_type = match address_type {
+            Some(_) => parse_address_type(address_type),
+            None => watch_only.address_type().unwrap_or(AccountAddressType::P2SHWPKH),
+        };
+        let pk = watch_only.get_child_pk(m, k).unwrap_or_else(|e| utils::fatal_kill(&e));
+        (pk, address_type, watch_only.child_origin(m, k), None)
+    } else {
+        let (master_acc, password) = init_master(opts, network);
+        let (path, address_type) = match path {
+            Some(p) => parse_path(p, address_type),
+            None => {
+                let address_type = parse_address_type(address_type);
+                let path = master_acc.child_path(&password, address_type, n, m, k, opts.legacy_paths).unwrap_or_else(|e| utils::fatal_kill(&e));
+                (path, address_type)
+            },
+        };
+        let pk = master_acc.get_child_pk(password.clone(), &path).unwrap();
+        let origin = master::key_origin(master_acc.fingerprint(), &path);
+        (pk, address_type, Some(origin), Some((master_acc, password)))
+    }
+}
+
+// Dice rolls, coin flips or hex entropy to be mixed with the OS RNG, with the amount of bits gathered
+fn get_user_entropy(sub_opts: &cli::NewMasterSubCommand, target_bits: usize) -> Option<Vec<u8>> {
+    let (input, symbols, bits_per_symbol) = if sub_opts.dice {
+        let (rolls, success) = io::get_secret("Enter your dice rolls (1-6): ", None);
+        if !success {
+            utils::fatal_kill("Failed to get dice rolls!");
+        }
+        (rolls, "123456", 6f64.log2())
+    } else if sub_opts.coins {
+        let (flips, success) = io::get_secret("Enter your coin flips (h/t): ", None);
+        if !success {
+            utils::fatal_kill("Failed to get coin flips!");
+        }
+        (flips.to_lowercase(), "ht", 1.0)
+    } else if sub_opts.entropy_hex {
+        let (hex, success) = io::get_secret("Enter your hex entropy: ", None);
+        if !success {
+            utils::fatal_kill("Failed to get hex entropy!");
+        }
+        (hex.to_lowercase(), "0123456789abcdef", 4.0)
+    } else {
+        return None;
+    };
+    let input: String = input.chars().filter(|c| !c.is_whitespace()).collect();
+    if let Some(c) = input.chars().find(|c| !symbols.contains(*c)) {
+        utils::fatal_kill(&format!("Invalid entropy input '{}'", c));
+    }
+    let bits = (input.len() as f64 * bits_per_symbol) as usize;
+    println!("{}Gathered:{} {} bits of entropy", style::Bold, style::Reset, bits);
+    if bits < target_bits {
+        utils::warn(&format!("Less than {} bits gathered, the result relies on the OS RNG for the rest", target_bits));
+    }
+    Some(input.into_bytes())
+}
+
+// BIP39 or SLIP-0039 passphrase extending the seed, only prompted for with --passphrase
+fn get_passphrase(opts: &cli::Opts, slip39: bool) -> Option<String> {
+    if !opts.with_passphrase {
+        return None;
+    }
+    let kind = match slip39 {
+        true => {
+            utils::warn("The passphrase is needed along with the shares, not the file encryption password. Shares combined without it (or with another one) give a different valid wallet.");
+            "SLIP-0039"
+        },
+        false => {
+            utils::warn("The passphrase is part of your seed, not the file encryption password. A different passphrase gives a different valid wallet.");
+            "BIP39"
+        },
+    };
+    let (passphrase, success) = io::get_secret(
+        &format!("Enter your {} passphrase: ", kind),
+        Some(&format!("Confirm your {} passphrase: ", kind))
+    );
+    if !success {
+        utils::fatal_kill("Failed to get passphrase!");
+    }
+    Some(passphrase)
+}
+
+// Group scheme from --groups, or a single N-of-M group from --min and --max
+fn get_share_groups(groups: &Option<String>, min: u8, max: u8) -> (u8, Vec<master::ShareGroup>) {
+    match groups {
+        Some(spec) => master::parse_groups(spec).unwrap_or_else(|e| utils::fatal_kill(&e)),
+        None => {
+            let groups = vec![master::ShareGroup {name: "shares".to_string(), threshold: min, count: max}];
+            master::check_groups(1, &groups).unwrap_or_else(|e| utils::fatal_kill(&format!("Invalid --min/--max: {}", e)));
+            (1, groups)
+        },
+    }
+}
+
+// Path of the wallet files in the data directory
+fn wallet_prefix(opts: &cli::Opts, label: &str) -> String {
+    let datadir = wallet::datadir(opts.datadir.as_ref()).unwrap_or_else(|e| utils::fatal_kill(&e));
+    wallet::prefix(&datadir, label).unwrap_or_else(|e| utils::fatal_kill(&e))
+}
+
+// Loads the encrypted files, the password is verified so it can be retried
+fn unlock_master(prefix: &str, network: Network, retries: u32, insecure: bool) -> (Master, String) {
+    if !wallet::exists(prefix) {
+        for f in &[prefix.to_string(), format!("{}-pub", prefix)] {
+            if !utils::file_exists(f) {
+                utils::fatal_kill(&format!("Wallet {} not found", wallet::filename(prefix)));
+            }
+        }
+    }
+    for f in wallet::key_files(prefix) {
+        if let Err(e) = utils::check_permissions(&f) {
+            match insecure {
+                true => utils::warn(&e),
+                false => utils::fatal_kill(&e),
+            }
+        }
+    }
+    // The container records its network in clear, no need to ask for the password first
+    if wallet::exists(prefix) {
+        let (_, recorded) = wallet::read_header(prefix).unwrap_or_else(|e| utils::fatal_kill(&e));
+        if recorded != network {
+            utils::fatal_kill(&format!("Wallet belongs to {} but {} was requested", recorded, network));
+        }
+    }
+    for attempt in 1..=retries {
+        let (password, success) = io::get_secret("Enter your password: ", None);
+        if !success {
+            utils::fatal_kill("Failed to get password!");
+        }
+        match Master::new_from_encrypted_files(&prefix.to_string(), password.clone(), network) {
+            Ok(master_acc) => return (master_acc, password),
+            Err(e) => {
+                if e != crypto::WRONG_PASSWORD {
+                    utils::fatal_kill(&e);
+                }
+                utils::warn(&format!("Wrong password ({}/{})", attempt, retries));
+            },
+        }
+    }
+    utils::fatal_kill("Too many wrong passwords");
+}
+
 fn init_master(opts: &cli::Opts, network: Network) -> (Master, String) {
+    if let Some(label) = &opts.label {
+        return unlock_master(&wallet_prefix(opts, label), network, opts.password_retries, opts.insecure_permissions);
+    }
     let (password, success) = io::get_secret(
         "Enter your password: ",
         Some("Confirm your password: ")
@@ -209,11 +759,9 @@ fn init_master(opts: &cli::Opts, network: Network) -> (Master, String) {
     if !success {
         utils::fatal_kill("Failed to get password!");
     }
-    if let Some(label) = &opts.label {
-        (Master::new_from_encrypted_files(label, password.clone()).unwrap(), password)
-    } else if let Some(words) = &opts.with_mnemonic {
-        (Master::new_from_inline_mnemonic(words.to_string(), password.clone(), network).unwrap(), password)
+    if let Some(words) = &opts.with_mnemonic {
+        (Master::new_from_inline_mnemonic(words.to_string(), password.clone(), network, get_passphrase(opts, false)).unwrap_or_else(|e| utils::fatal_kill(&e)), password)
     } else {
-        (Master::new_from_mnemonic(password.clone(), network, opts.shamir_shares).unwrap(), password)
+        (Master::new_from_mnemonic(password.clone(), network, opts.shamir_shares, get_passphrase(opts, opts.shamir_shares.is_some())).unwrap_or_else(|e| utils::fatal_kill(&e)), password)
     }
 }
\ No newline at end of file
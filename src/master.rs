@@ -3,6 +3,7 @@ use bitcoin_wallet::{
     account::{
         Account,
         MasterAccount,
+        Seed,
         Unlocker,
         AccountAddressType,
         MasterKeyEntropy,
@@ -14,22 +15,42 @@ use bitcoin_wallet::{
     error::Error,
 };
 use bitcoin::{
-    PublicKey, blockdata::transaction::{SigHashType, Transaction, TxOut},
+    Address, PrivateKey, PublicKey, blockdata::transaction::{SigHashType, Transaction, TxOut},
     network::constants::Network,
-    util::bip32::ExtendedPubKey,
+    secp256k1::Secp256k1,
+    util::{
+        base58,
+        bip32::{ChildNumber, DerivationPath, ExtendedPrivKey, ExtendedPubKey, Fingerprint},
+    },
 };
-use rand::Rng;
+use std::str::FromStr;
+use rand::{Rng, RngCore, rngs::OsRng};
+use bitcoin_hashes::{sha512, Hash};
 use termion::{
     color,
     style
 };
 
+use crate::bip85;
 use crate::io;
 use crate::crypto;
+use crate::keys::ImportedKey;
 use crate::utils;
+use crate::wallet::{self, WalletState};
+use crate::wordlist;
 
 pub struct Master {
     encrypted: MasterAccount,
+    imported: Vec<ImportedKey>,
+    state: WalletState,
+    network: Network,
+}
+
+// Account level extended public key, derives child keys without any private material
+pub struct WatchOnly {
+    xpub: ExtendedPubKey,
+    origin: Option<(Fingerprint, DerivationPath)>,
+    address_type: Option<AccountAddressType>,
 }
 
 impl Master {
@@ -37,20 +58,54 @@ impl Master {
         password: String,
         entropy: MasterKeyEntropy,
         network: Network,
+        passphrase: Option<String>,
+        user_entropy: Option<Vec<u8>>,
+        check_backup: bool,
     ) -> Result<Master, String> {
-        let mnemonic = Mnemonic::new_random(entropy).unwrap();
-        io::show_new_mnemonic(&mnemonic);
-        let encrypted = MasterAccount::from_mnemonic(&mnemonic, 0, network, &password, None).unwrap();
+        let mnemonic = match user_entropy {
+            Some(u) => Mnemonic::new(&mix_entropy(&u, entropy as usize)).unwrap(),
+            None => Mnemonic::new_random(entropy).unwrap(),
+        };
+        io::show_new_mnemonic(&mnemonic, check_backup);
+        let encrypted = MasterAccount::from_mnemonic(&mnemonic, 0, network, &password, passphrase.as_deref()).unwrap();
         Ok(Master{
             encrypted,
+            imported: vec![],
+            state: WalletState::default(),
+            network,
         })
     }
 
-    pub fn new_from_encrypted_files(prefix: &String, password: String) -> Result<Master, String> {
-        let (encrypted_master, pubkey) = crypto::import_encrypted_keys(prefix, &password);
+    // Loads the wallet container, or the loose files written by older versions.
+    // The network recorded at export time must match the requested one
+    pub fn new_from_encrypted_files(prefix: &String, password: String, network: Network) -> Result<Master, String> {
+        let (encrypted_master, pubkey, imported, state) = match wallet::exists(prefix) {
+            true => {
+                let (recorded, encrypted_master, pubkey, imported, state) = wallet::load(prefix, &password)?;
+                if recorded != network {
+                    return Err(format!("Wallet belongs to {} but {} was requested", recorded, network));
+                }
+                (encrypted_master, pubkey, imported, state)
+            },
+            false => {
+                let (encrypted_master, pubkey) = crypto::import_encrypted_keys(prefix, &password)?;
+                // Older files only tell mainnet from the test networks
+                if (pubkey.network == Network::Bitcoin) != (network == Network::Bitcoin) {
+                    return Err(format!("Wallet belongs to {} but {} was requested", pubkey.network, network));
+                }
+                let imported = crypto::import_encrypted_imported_keys(prefix, &password)?;
+                (encrypted_master, pubkey, imported, WalletState::default())
+            },
+        };
         let encrypted = MasterAccount::from_encrypted(&encrypted_master, pubkey, 0x0);
+        if let Err(_) = Unlocker::new_for_master(&encrypted, &password) {
+            return Err(crypto::WRONG_PASSWORD.to_string());
+        }
         Ok(Master {
             encrypted,
+            imported,
+            state,
+            network,
         })
     }
 
@@ -58,19 +113,16 @@ impl Master {
         password: String,
         network: Network,
         shamir_sharing: Option<u8>,
+        passphrase: Option<String>,
     ) -> Result<Master, String> {
         match shamir_sharing {
-            Some(s) => Self::new_from_ss_mnemonic(password, network, s),
+            Some(s) => Self::new_from_ss_mnemonic(password, network, s, passphrase),
             None => {
                 let (words, success) = io::get_secret("Enter your words: ", None);
-                if success {
-                    if let Ok(master) = Self::new_from_inline_mnemonic(words, password, network) {
-                        return Ok(master);
-                    }
-                } else {
+                if !success {
                     utils::fatal_kill("Failed to get words!");
                 }
-                return Err("Failed to init root from mnemonic".to_string());
+                Self::new_from_inline_mnemonic(words, password, network, passphrase)
             }
         }
     }
@@ -79,34 +131,44 @@ impl Master {
         mnemonic: String,
         password: String,
         network: Network,
+        passphrase: Option<String>,
     ) -> Result<Master, String> {
-        let mnemonic = Mnemonic::from_str(&mnemonic).unwrap();
-        let encrypted = MasterAccount::from_mnemonic(&mnemonic, 0, network, &password, None).unwrap();
+        let mnemonic = mnemonic.to_lowercase();
+        let words: Vec<&str> = mnemonic.split_whitespace().collect();
+        if let Err(e) = wordlist::validate(&words) {
+            utils::warn(&e);
+            wordlist::assist(&words);
+            return Err(e);
+        }
+        let mnemonic = match Mnemonic::from_str(&words.join(" ")) {
+            Ok(m) => m,
+            Err(e) => return Err(e.to_string()),
+        };
+        let encrypted = MasterAccount::from_mnemonic(&mnemonic, 0, network, &password, passphrase.as_deref()).unwrap();
         Ok(Master {
             encrypted,
+            imported: vec![],
+            state: WalletState::default(),
+            network,
         })
     }
 
+    // Reads up to n shares, stopping as soon as the group thresholds are met
     fn new_from_ss_mnemonic(
         password: String,
         network: Network,
         n: u8,
+        passphrase: Option<String>,
     ) -> Result<Master, String> {
-        let mut shares: Vec<Share> = vec![];
-        for x in 0..n {
-            println!("{}{}Input share #{}{}", style::Bold, color::Fg(color::Blue), x+1, style::Reset);
-            let (words, success) = io::get_secret("Enter your words: ", None);
-            if success {
-                let share = Share::from_mnemonic(&words).unwrap();
-                shares.push(share);
-            } else {
-                utils::fatal_kill("Failed to get words!");
-            }
-        }
-        let seed = ShamirSecretSharing::combine(&shares, None).unwrap();
+        let shares = read_shares(Some(n), false);
+        let selected = select_shares(&shares).ok_or("Not enough shares to meet the group thresholds")?;
+        let seed = ShamirSecretSharing::combine(&selected, passphrase.as_deref()).map_err(|e| e.to_string())?;
         let encrypted = MasterAccount::from_seed(&seed, 0, network, &password).unwrap();
         Ok(Master {
             encrypted,
+            imported: vec![],
+            state: WalletState::default(),
+            network,
         })
     }
 
@@ -114,59 +176,168 @@ impl Master {
         password: String,
         entropy: MasterKeyEntropy,
         network: Network,
-        n: u8,
-        m: u8,
+        group_threshold: u8,
+        groups: &[ShareGroup],
+        user_entropy: Option<Vec<u8>>,
+        passphrase: Option<String>,
+        check_backup: bool,
     ) -> Result<Master, String> {
-        let encrypted = MasterAccount::new(entropy, network, &password).unwrap();
-        let seed = encrypted.seed(network, &password).unwrap();
-        let shares = ShamirSecretSharing::generate(1, &[(n, m)], &seed, None, 1).unwrap();
+        let encrypted = match user_entropy {
+            Some(u) => MasterAccount::from_seed(&Seed(mix_entropy(&u, entropy as usize)), 0, network, &password).unwrap(),
+            None => MasterAccount::new(entropy, network, &password).unwrap(),
+        };
+        let master = Master {
+            encrypted,
+            imported: vec![],
+            state: WalletState::default(),
+            network,
+        };
+        master.split(&password, group_threshold, groups, passphrase, check_backup)?;
+        Ok(master)
+    }
+
+    // Splits the seed into SLIP-0039 shares, checks a random recombination and shows them
+    pub fn split(
+        &self,
+        password: &str,
+        group_threshold: u8,
+        groups: &[ShareGroup],
+        passphrase: Option<String>,
+        check_backup: bool,
+    ) -> Result<(), String> {
+        let seed = self.encrypted.seed(self.network, password).map_err(|e| e.to_string())?;
+        let rules: Vec<(u8, u8)> = groups.iter().map(|g| (g.threshold, g.count)).collect();
+        let shares = ShamirSecretSharing::generate(group_threshold, &rules, &seed, passphrase.as_deref(), 1).map_err(|e| e.to_string())?;
+        // Recombine a random selection of groups and members to check the shares
         let mut re_shares: Vec<Share> = Vec::new();
-        let mut indexes: Vec<u8> = Vec::new();
-        for _ in 0..n {
-            let mut index = rand::thread_rng().gen_range(0..m);
-            while indexes.iter().any(|&i| i == index) {
-                index = rand::thread_rng().gen_range(0..m);
+        let mut group_indexes: Vec<u8> = Vec::new();
+        for _ in 0..group_threshold {
+            let mut g = rand::thread_rng().gen_range(0..groups.len() as u8);
+            while group_indexes.iter().any(|&i| i == g) {
+                g = rand::thread_rng().gen_range(0..groups.len() as u8);
+            }
+            group_indexes.push(g);
+            let members: Vec<&Share> = shares.iter().filter(|s| s.group_index == g).collect();
+            let mut indexes: Vec<usize> = Vec::new();
+            for _ in 0..groups[g as usize].threshold {
+                let mut index = rand::thread_rng().gen_range(0..members.len());
+                while indexes.iter().any(|&i| i == index) {
+                    index = rand::thread_rng().gen_range(0..members.len());
+                }
+                re_shares.push(members[index].clone());
+                indexes.push(index);
             }
-            re_shares.push(shares[index as usize].clone());
-            indexes.push(index);
-        }
-        let re_seed = ShamirSecretSharing::combine(&re_shares, None).unwrap();
-        let re_master = MasterAccount::from_seed(&re_seed, 0, network, &password).unwrap();
-        assert_eq!(encrypted.master_public(), re_master.master_public());
-        assert_eq!(encrypted.encrypted(), re_master.encrypted());
-        let mut i = 0;
-        for share in shares {
-            let mnemonic = share.to_mnemonic();
-            let words: Vec<&str> = mnemonic.split(" ").collect();
-            println!("{}{}Showing share #{}{}", style::Bold, color::Fg(color::Blue), i+1, style::Reset);
-            io::show_new_mnemonic_from_words(&words);
-            println!("{}[DONE]{}                                                          ", color::Fg(color::Green), style::Reset);
-            i = i+1;
         }
-        Ok(Master {
-            encrypted,
-        })
+        let re_seed = ShamirSecretSharing::combine(&re_shares, passphrase.as_deref()).map_err(|e| e.to_string())?;
+        let re_master = MasterAccount::from_seed(&re_seed, 0, self.network, password).map_err(|e| e.to_string())?;
+        if self.encrypted.master_public() != re_master.master_public() {
+            return Err("Recombined shares do not match the wallet".to_string());
+        }
+        if groups.len() > 1 {
+            println!("{}{} of {} groups are needed to recover the wallet{}", style::Bold, group_threshold, groups.len(), style::Reset);
+        }
+        for (g, group) in groups.iter().enumerate() {
+            let members: Vec<&Share> = shares.iter().filter(|s| s.group_index == g as u8).collect();
+            for (i, share) in members.iter().enumerate() {
+                let mnemonic = share.to_mnemonic();
+                let words: Vec<&str> = mnemonic.split(" ").collect();
+                match groups.len() {
+                    1 => println!("{}{}Showing share #{}{}", style::Bold, color::Fg(color::Blue), i+1, style::Reset),
+                    _ => println!("{}{}Showing share #{} of group {} ({} of {}){}",
+                        style::Bold, color::Fg(color::Blue), i+1, group.name, group.threshold, group.count, style::Reset),
+                }
+                io::show_new_mnemonic_from_words(&words, check_backup);
+                println!("{}[DONE]{}                                                          ", color::Fg(color::Green), style::Reset);
+            }
+        }
+        Ok(())
     }
 
     pub fn get_master_public(&self) -> &ExtendedPubKey {
         &self.encrypted.master_public()
     }
 
-    pub fn new_account(
-        &self,
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.encrypted.master_public().fingerprint()
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    pub fn export_master(self, password: String, prefix: &str) {
+        self.save(password, prefix);
+    }
+
+    pub fn save(&self, password: String, prefix: &str) {
+        let res = wallet::save(
+            prefix,
+            &password,
+            self.network,
+            self.encrypted.encrypted(),
+            self.encrypted.master_public(),
+            &self.imported,
+            &self.state,
+        );
+        if let Err(e) = res {
+            utils::fatal_kill(&e);
+        }
+    }
+
+    // Re-encrypts the seed, the rest of the wallet is re-encrypted on save
+    pub fn change_password(&mut self, old_password: &str, new_password: &str) -> Result<(), Error> {
+        let network = self.network();
+        let seed = self.encrypted.seed(network, old_password)?;
+        let encrypted = MasterAccount::from_seed(&seed, 0, network, new_password)?;
+        if encrypted.master_public() != self.encrypted.master_public() {
+            return Err(Error::Passphrase);
+        }
+        self.encrypted = encrypted;
+        Ok(())
+    }
+
+    pub fn state(&self) -> &WalletState {
+        &self.state
+    }
+
+    pub fn set_birthday(&mut self, height: Option<u64>) {
+        self.state.birthday = height;
+    }
+
+    pub fn set_address_label(&mut self, address: String, label: String) {
+        self.state.labels.insert(address, label);
+    }
+
+    // Tracks the next unused index and the descriptors of accounts in use
+    pub fn record_usage(
+        &mut self,
         password: String,
         address_type: AccountAddressType,
         n: u32,
         m: u32,
-    ) -> Account {
-        let mut unlocker = Unlocker::new_for_master(&self.encrypted, &password).unwrap();
-        Account::new(&mut unlocker, address_type, n, m, 10).unwrap()
+        k: u32,
+        legacy: bool,
+    ) -> Result<(), String> {
+        if self.state.record_usage(address_type.as_u32(), n, m, k) {
+            let path = self.account_path(&password, address_type, n, legacy)?;
+            let xpub = self.get_account_public(password, &path).map_err(|e| e.to_string())?;
+            let origin = key_origin(self.fingerprint(), &path);
+            for chain in 0..2 {
+                self.state.descriptors.push(descriptor(address_type, &origin, &xpub, chain));
+            }
+        }
+        Ok(())
     }
 
-    pub fn export_master(self, password: String, prefix: &str) {
-        crypto::export_encrypted_keys(&self.encrypted, prefix, &password);
+    pub fn import_key(&mut self, key: ImportedKey) {
+        self.imported.push(key);
     }
 
+    pub fn imported_keys(&self) -> &Vec<ImportedKey> {
+        &self.imported
+    }
+
+
     pub fn export_account(
         self,
         password: String,
@@ -204,12 +375,627 @@ impl Master {
     pub fn get_child_pk(
         &self,
         password: String,
+        path: &DerivationPath,
+    ) -> Result<PublicKey, Error> {
+        let unlocker = Unlocker::new_for_master(&self.encrypted, &password)?;
+        let secp = Secp256k1::new();
+        let privkey = unlocker.master_private().derive_priv(&secp, path)?;
+        Ok(ExtendedPubKey::from_private(&secp, &privkey).public_key)
+    }
+
+    pub fn get_child_privkey(
+        &self,
+        password: String,
+        path: &DerivationPath,
+    ) -> Result<PrivateKey, Error> {
+        let unlocker = Unlocker::new_for_master(&self.encrypted, &password)?;
+        let secp = Secp256k1::new();
+        Ok(unlocker.master_private().derive_priv(&secp, path)?.private_key)
+    }
+
+    pub fn bip85_entropy(
+        &self,
+        password: String,
+        path: &DerivationPath,
+    ) -> Result<Vec<u8>, String> {
+        let unlocker = match Unlocker::new_for_master(&self.encrypted, &password) {
+            Ok(u) => u,
+            Err(e) => return Err(e.to_string()),
+        };
+        bip85::entropy(unlocker.master_private(), path)
+    }
+
+    pub fn get_account_public(
+        &self,
+        password: String,
+        path: &DerivationPath,
+    ) -> Result<ExtendedPubKey, Error> {
+        let unlocker = Unlocker::new_for_master(&self.encrypted, &password)?;
+        let secp = Secp256k1::new();
+        let privkey = unlocker.master_private().derive_priv(&secp, path)?;
+        Ok(ExtendedPubKey::from_private(&secp, &privkey))
+    }
+
+    // Looks for the address over accounts, chains and indexes of every given script type
+    pub fn find_address(
+        &self,
+        password: String,
+        address: &Address,
+        address_types: &[AccountAddressType],
+        max_account: u32,
+        max_index: u32,
+        legacy: bool,
+    ) -> Result<Option<(DerivationPath, AccountAddressType)>, String> {
+        let unlocker = Unlocker::new_for_master(&self.encrypted, &password).map_err(|e| e.to_string())?;
+        let secp = Secp256k1::new();
+        let network = self.network;
+        let target = address.script_pubkey();
+        for &address_type in address_types {
+            for n in 0..=max_account {
+                for m in 0..2 {
+                    let path = self.chain_path(&password, address_type, n, m, legacy)?;
+                    let privkey = unlocker.master_private().derive_priv(&secp, &path).map_err(|e| e.to_string())?;
+                    let chain = ExtendedPubKey::from_private(&secp, &privkey);
+                    for k in 0..=max_index {
+                        let pk = chain.ckd_pub(&secp, ChildNumber::Normal { index: k }).map_err(|e| e.to_string())?.public_key;
+                        if to_address(address_type, &pk, network).script_pubkey() == target {
+                            let mut child: Vec<ChildNumber> = path.into();
+                            child.push(ChildNumber::Normal { index: k });
+                            return Ok(Some((DerivationPath::from(child), address_type)));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // Older versions derived keys through Unlocker::sub_account_key, which only returns the key.
+    // Key origins, descriptors, account xpubs and private key export all need the explicit path,
+    // so it is found by comparing that key with the layouts the crate versions used. An unknown
+    // layout is an error, commands derive their paths before printing anything
+    fn legacy_chain_path(
+        &self,
+        password: &str,
+        address_type: AccountAddressType,
+        n: u32,
+        m: u32,
+    ) -> Result<DerivationPath, String> {
+        let mut unlocker = Unlocker::new_for_master(&self.encrypted, password).map_err(|e| e.to_string())?;
+        let target = unlocker.sub_account_key(address_type, n, m).map_err(|e| e.to_string())?;
+        let secp = Secp256k1::new();
+        for &coin in &[0, 1] {
+            for &chain in &[ChildNumber::Normal { index: m }, ChildNumber::Hardened { index: m }] {
+                let path = DerivationPath::from(vec![
+                    ChildNumber::Hardened { index: address_type.as_u32() },
+                    ChildNumber::Hardened { index: coin },
+                    ChildNumber::Hardened { index: n },
+                    chain,
+                ]);
+                if unlocker.master_private().derive_priv(&secp, &path).map_err(|e| e.to_string())? == target {
+                    return Ok(path);
+                }
+            }
+        }
+        Err("Cannot find the derivation path used by older versions".to_string())
+    }
+
+    // m/purpose'/coin'/n'/m
+    fn chain_path(
+        &self,
+        password: &str,
+        address_type: AccountAddressType,
+        n: u32,
+        m: u32,
+        legacy: bool,
+    ) -> Result<DerivationPath, String> {
+        match legacy {
+            true => self.legacy_chain_path(password, address_type, n, m),
+            false => {
+                let mut path: Vec<ChildNumber> = account_path(address_type, self.network, n).into();
+                path.push(ChildNumber::Normal { index: m });
+                Ok(DerivationPath::from(path))
+            },
+        }
+    }
+
+    // m/purpose'/coin'/n', as derived by older versions when legacy
+    pub fn account_path(
+        &self,
+        password: &str,
+        address_type: AccountAddressType,
+        n: u32,
+        legacy: bool,
+    ) -> Result<DerivationPath, String> {
+        if !legacy {
+            return Ok(account_path(address_type, self.network, n));
+        }
+        let path: Vec<ChildNumber> = self.legacy_chain_path(password, address_type, n, 0)?.into();
+        if let ChildNumber::Hardened { .. } = path[3] {
+            return Err("Older versions used hardened sub accounts, there is no account level key".to_string());
+        }
+        Ok(DerivationPath::from(path[0..3].to_vec()))
+    }
+
+    // m/purpose'/coin'/n'/m/k, as derived by older versions when legacy
+    pub fn child_path(
+        &self,
+        password: &str,
         address_type: AccountAddressType,
         n: u32,
         m: u32,
         k: u32,
-    ) -> Result<PublicKey, Error> {
-        let acc = self.new_account(password, address_type, n, m);
-        acc.compute_base_public_key(k)
+        legacy: bool,
+    ) -> Result<DerivationPath, String> {
+        let mut path: Vec<ChildNumber> = self.chain_path(password, address_type, n, m, legacy)?.into();
+        path.push(ChildNumber::Normal { index: k });
+        Ok(DerivationPath::from(path))
+    }
+}
+
+pub fn to_address(address_type: AccountAddressType, pk: &PublicKey, network: Network) -> Address {
+    match address_type {
+        AccountAddressType::P2PKH => Address::p2pkh(pk, network),
+        AccountAddressType::P2SHWPKH => Address::p2shwpkh(pk, network),
+        AccountAddressType::P2WPKH | _ => Address::p2wpkh(pk, network),
     }
-}
\ No newline at end of file
+}
+
+// SLIP-0039 group of shares, the name is only shown to the user
+pub struct ShareGroup {
+    pub name: String,
+    pub threshold: u8,
+    pub count: u8,
+}
+
+// Parses a group scheme, e.g. 2:family=2/3,lawyers=1/1,safe=3/5 for 2 of 3 groups
+pub fn parse_groups(spec: &str) -> Result<(u8, Vec<ShareGroup>), String> {
+    let invalid = |reason: &str| format!("Invalid group scheme {}: {}", spec, reason);
+    let parts: Vec<&str> = spec.splitn(2, ':').collect();
+    if parts.len() != 2 {
+        return Err(invalid("expected T:name=N/M,..."));
+    }
+    let group_threshold = u8::from_str(parts[0]).map_err(|_| invalid("bad group threshold"))?;
+    let mut groups: Vec<ShareGroup> = vec![];
+    for group in parts[1].split(',') {
+        let (name, rule) = match group.find('=') {
+            Some(i) => (group[..i].to_string(), &group[i+1..]),
+            None => (format!("#{}", groups.len()+1), group),
+        };
+        let rule: Vec<&str> = rule.split('/').collect();
+        if rule.len() != 2 {
+            return Err(invalid(&format!("expected N/M for group {}", name)));
+        }
+        let threshold = u8::from_str(rule[0]).map_err(|_| invalid(&format!("bad threshold for group {}", name)))?;
+        let count = u8::from_str(rule[1]).map_err(|_| invalid(&format!("bad share count for group {}", name)))?;
+        groups.push(ShareGroup { name, threshold, count });
+    }
+    check_groups(group_threshold, &groups).map_err(|e| invalid(&e))?;
+    Ok((group_threshold, groups))
+}
+
+// SLIP-0039 limits, for group schemes and plain N-of-M shares alike
+pub fn check_groups(group_threshold: u8, groups: &[ShareGroup]) -> Result<(), String> {
+    for group in groups {
+        if group.threshold == 0 || group.threshold > group.count || group.count > 16 {
+            return Err(format!("group {} needs 1 <= N <= M <= 16", group.name));
+        }
+        if group.threshold == 1 && group.count > 1 {
+            return Err(format!("group {} with a threshold of 1 can only have 1 share", group.name));
+        }
+    }
+    if group_threshold == 0 || group_threshold as usize > groups.len() || groups.len() > 16 {
+        return Err("needs 1 <= T <= number of groups <= 16".to_string());
+    }
+    Ok(())
+}
+
+// Reads shares one by one until the group thresholds are met, at most max of them
+// or until an empty input, details shows the metadata of each share
+fn read_shares(max: Option<u8>, details: bool) -> Vec<Share> {
+    let mut shares: Vec<Share> = vec![];
+    while max.map_or(true, |n| shares.len() < n as usize) {
+        println!("{}{}Input share #{}{}", style::Bold, color::Fg(color::Blue), shares.len()+1, style::Reset);
+        let (words, success) = io::get_secret("Enter your words: ", None);
+        if !success {
+            utils::fatal_kill("Failed to get words!");
+        }
+        if words.trim().is_empty() {
+            break;
+        }
+        let share = match Share::from_mnemonic(words.trim()) {
+            Ok(s) => s,
+            Err(e) => {
+                utils::warn(&format!("Invalid share: {}", e));
+                continue;
+            }
+        };
+        if details {
+            println!("  Identifier {}, group {} of {} ({} needed), member {} ({} needed)",
+                share.id, share.group_index+1, share.group_count, share.group_threshold, share.member_index+1, share.member_threshold);
+        }
+        if shares.iter().any(|s| s.id != share.id) {
+            utils::warn("Share belongs to another set of shares");
+            continue;
+        }
+        if shares.iter().any(|s| s.group_index == share.group_index && s.member_index == share.member_index) {
+            utils::warn("Share already entered");
+            continue;
+        }
+        shares.push(share);
+        if show_ss_progress(&shares) {
+            break;
+        }
+    }
+    shares
+}
+
+// Checks the shares and returns the fingerprint of the master key they recover,
+// nothing is encrypted nor written
+pub fn verify_shares(network: Network, passphrase: Option<String>) -> Result<Fingerprint, String> {
+    println!("Enter the shares one by one, leave empty to stop");
+    let shares = read_shares(None, true);
+    let selected = select_shares(&shares).ok_or("Not enough shares to meet the group thresholds")?;
+    let seed = ShamirSecretSharing::combine(&selected, passphrase.as_deref()).map_err(|e| e.to_string())?;
+    let secp = Secp256k1::new();
+    let master = ExtendedPrivKey::new_master(network, &seed.0).map_err(|e| e.to_string())?;
+    Ok(master.fingerprint(&secp))
+}
+
+// Master fingerprint of a BIP39 mnemonic, without creating a wallet
+pub fn mnemonic_fingerprint(words: &[&str], network: Network, passphrase: Option<String>) -> Result<Fingerprint, String> {
+    wordlist::validate(words)?;
+    let mnemonic = Mnemonic::from_str(&words.join(" ")).map_err(|e| e.to_string())?;
+    let seed = mnemonic.to_seed(passphrase.as_deref());
+    let secp = Secp256k1::new();
+    let master = ExtendedPrivKey::new_master(network, &seed.0).map_err(|e| e.to_string())?;
+    Ok(master.fingerprint(&secp))
+}
+
+// Checks a single share and describes it, it cannot be matched with a wallet on its own
+pub fn describe_share(words: &[&str]) -> Result<String, String> {
+    let share = Share::from_mnemonic(&words.join(" ")).map_err(|e| e.to_string())?;
+    Ok(format!("SLIP-0039 share {}, group {} of {} ({} needed), member {} ({} needed)",
+        share.id, share.group_index+1, share.group_count, share.group_threshold, share.member_index+1, share.member_threshold))
+}
+
+// Prints the groups collected so far, returns true once enough groups are complete
+fn show_ss_progress(shares: &[Share]) -> bool {
+    let group_threshold = shares[0].group_threshold;
+    let mut group_indexes: Vec<u8> = shares.iter().map(|s| s.group_index).collect();
+    group_indexes.sort();
+    group_indexes.dedup();
+    let mut complete = 0;
+    for g in group_indexes {
+        let members: Vec<&Share> = shares.iter().filter(|s| s.group_index == g).collect();
+        let threshold = members[0].member_threshold;
+        if members.len() >= threshold as usize {
+            complete += 1;
+        }
+        println!("  Group {}: {} of {} shares", g+1, std::cmp::min(members.len(), threshold as usize), threshold);
+    }
+    println!("{}{} of {} groups complete{}", style::Bold, complete, group_threshold, style::Reset);
+    complete >= group_threshold
+}
+
+// Exactly the threshold of shares from the threshold of complete groups, as combining expects
+fn select_shares(shares: &[Share]) -> Option<Vec<Share>> {
+    let group_threshold = shares.first()?.group_threshold;
+    let mut selected: Vec<Share> = vec![];
+    let mut complete = 0;
+    for g in 0..shares[0].group_count {
+        let members: Vec<&Share> = shares.iter().filter(|s| s.group_index == g).collect();
+        if complete < group_threshold && members.len() > 0 && members.len() >= members[0].member_threshold as usize {
+            selected.extend(members.iter().take(members[0].member_threshold as usize).map(|&s| s.clone()));
+            complete += 1;
+        }
+    }
+    match complete >= group_threshold {
+        true => Some(selected),
+        false => None,
+    }
+}
+
+// User entropy (dice, coin flips) is hashed together with the OS RNG,
+// so a bad source on either side cannot weaken the result
+pub fn mix_entropy(user_entropy: &[u8], len: usize) -> Vec<u8> {
+    let mut os_entropy = vec![0u8; len];
+    OsRng.fill_bytes(&mut os_entropy);
+    let mut data = os_entropy;
+    data.extend_from_slice(user_entropy);
+    sha512::Hash::hash(&data).into_inner()[0..len].to_vec()
+}
+
+// SLIP-0044 coin type, testnet and regtest share the same one
+pub fn coin_type(network: Network) -> u32 {
+    match network {
+        Network::Bitcoin => 0,
+        Network::Testnet | Network::Regtest => 1,
+    }
+}
+
+// m/purpose'/coin'/n', the BIP44 / BIP49 / BIP84 purpose follows the script type
+pub fn account_path(
+    address_type: AccountAddressType,
+    network: Network,
+    n: u32,
+) -> DerivationPath {
+    DerivationPath::from(vec![
+        ChildNumber::Hardened { index: address_type.as_u32() },
+        ChildNumber::Hardened { index: coin_type(network) },
+        ChildNumber::Hardened { index: n },
+    ])
+}
+
+impl WatchOnly {
+    // Accepts SLIP-0132 encodings and an optional [fingerprint/path] origin prefix
+    pub fn from_str(key: &str, network: Network) -> Result<WatchOnly, String> {
+        let mut origin = None;
+        let mut encoded = key.trim();
+        if encoded.starts_with('[') {
+            let end = match encoded.find(']') {
+                Some(e) => e,
+                None => return Err("Invalid key origin, missing ']'".to_string()),
+            };
+            origin = Some(parse_key_origin(&encoded[1..end])?);
+            encoded = &encoded[end+1..];
+        }
+        let address_type = match encoded.get(0..4) {
+            Some("ypub") | Some("upub") => Some(AccountAddressType::P2SHWPKH),
+            Some("zpub") | Some("vpub") => Some(AccountAddressType::P2WPKH),
+            Some("xpub") | Some("tpub") => None,
+            _ => return Err("Unknown extended public key encoding".to_string()),
+        };
+        let mainnet = match &encoded[0..1] {
+            "x" | "y" | "z" => true,
+            _ => false,
+        };
+        if mainnet != (network == Network::Bitcoin) {
+            return Err("Extended public key does not belong to the selected network".to_string());
+        }
+        let mut data = base58::from_check(encoded).map_err(|e| e.to_string())?;
+        if data.len() < 4 {
+            return Err("Invalid extended public key".to_string());
+        }
+        let version = match mainnet {
+            true => slip132_version("xpub").unwrap(),
+            false => slip132_version("tpub").unwrap(),
+        };
+        data[0..4].copy_from_slice(&version);
+        let xpub = ExtendedPubKey::from_str(&base58::check_encode_slice(&data)).map_err(|e| e.to_string())?;
+        Ok(WatchOnly {
+            xpub,
+            origin,
+            address_type,
+        })
+    }
+
+    // Script type implied by the key encoding, if any
+    pub fn address_type(&self) -> Option<AccountAddressType> {
+        self.address_type
+    }
+
+    pub fn get_child_pk(&self, m: u32, k: u32) -> Result<PublicKey, String> {
+        let secp = Secp256k1::verification_only();
+        let path = vec![ChildNumber::Normal { index: m }, ChildNumber::Normal { index: k }];
+        match self.xpub.derive_pub(&secp, &path) {
+            Ok(key) => Ok(key.public_key),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    // Looks for the address over both chains of the account, returns the chain and index
+    pub fn find_address(
+        &self,
+        address: &Address,
+        address_types: &[AccountAddressType],
+        max_index: u32,
+    ) -> Result<Option<(u32, u32, AccountAddressType)>, String> {
+        let secp = Secp256k1::verification_only();
+        let network = address.network;
+        let target = address.script_pubkey();
+        for &address_type in address_types {
+            for m in 0..2 {
+                let chain = self.xpub.ckd_pub(&secp, ChildNumber::Normal { index: m }).map_err(|e| e.to_string())?;
+                for k in 0..=max_index {
+                    let pk = chain.ckd_pub(&secp, ChildNumber::Normal { index: k }).map_err(|e| e.to_string())?.public_key;
+                    if to_address(address_type, &pk, network).script_pubkey() == target {
+                        return Ok(Some((m, k, address_type)));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // Full key origin of a child, unknown when the key was given without its origin
+    pub fn child_origin(&self, m: u32, k: u32) -> Option<String> {
+        let (fingerprint, path) = self.origin.as_ref()?;
+        let mut full: Vec<ChildNumber> = path.clone().into();
+        full.push(ChildNumber::Normal { index: m });
+        full.push(ChildNumber::Normal { index: k });
+        Some(key_origin(*fingerprint, &DerivationPath::from(full)))
+    }
+}
+
+// Parses a custom derivation path and checks its purpose against the script type,
+// which is inferred from the purpose when not given
+pub fn parse_path(
+    path: &str,
+    address_type: Option<AccountAddressType>,
+) -> Result<(DerivationPath, AccountAddressType), String> {
+    let path = match DerivationPath::from_str(path) {
+        Ok(p) => p,
+        Err(e) => return Err(format!("Invalid derivation path {}: {}", path, e)),
+    };
+    let implied = match path.as_ref().first() {
+        Some(&ChildNumber::Hardened { index }) => match AccountAddressType::from_u32(index) {
+            AccountAddressType::P2WSH(_) => None,
+            a => Some(a),
+        },
+        Some(_) => None,
+        None => return Err("Derivation path cannot be empty".to_string()),
+    };
+    match (address_type, implied) {
+        (Some(a), Some(i)) if a != i => Err(format!("Derivation path {} is not valid for the address type", path)),
+        (Some(a), _) => Ok((path, a)),
+        (None, Some(i)) => Ok((path, i)),
+        (None, None) => {
+            utils::warn("Non-standard derivation path purpose, using P2SH-P2WPKH addresses");
+            Ok((path, AccountAddressType::P2SHWPKH))
+        },
+    }
+}
+
+// Output descriptor of an account chain, e.g. wpkh([d34db33f/84'/0'/0']xpub.../0/*)
+pub fn descriptor(
+    address_type: AccountAddressType,
+    origin: &str,
+    xpub: &ExtendedPubKey,
+    chain: u32,
+) -> String {
+    let key = format!("{}{}/{}/*", origin, xpub, chain);
+    match address_type {
+        AccountAddressType::P2PKH => format!("pkh({})", key),
+        AccountAddressType::P2SHWPKH => format!("sh(wpkh({}))", key),
+        AccountAddressType::P2WPKH | _ => format!("wpkh({})", key),
+    }
+}
+
+// SLIP-0132 version bytes
+fn slip132_version(encoding: &str) -> Option<[u8; 4]> {
+    match encoding {
+        "xpub" => Some([0x04, 0x88, 0xb2, 0x1e]),
+        "ypub" => Some([0x04, 0x9d, 0x7c, 0xb2]),
+        "zpub" => Some([0x04, 0xb2, 0x47, 0x46]),
+        "tpub" => Some([0x04, 0x35, 0x87, 0xcf]),
+        "upub" => Some([0x04, 0x4a, 0x52, 0x62]),
+        "vpub" => Some([0x04, 0x5f, 0x1c, 0xf6]),
+        _ => None,
+    }
+}
+
+pub fn default_encoding(address_type: AccountAddressType, network: Network) -> &'static str {
+    match (address_type, network) {
+        (AccountAddressType::P2SHWPKH, Network::Bitcoin) => "ypub",
+        (AccountAddressType::P2WPKH, Network::Bitcoin) => "zpub",
+        (_, Network::Bitcoin) => "xpub",
+        (AccountAddressType::P2SHWPKH, _) => "upub",
+        (AccountAddressType::P2WPKH, _) => "vpub",
+        (_, _) => "tpub",
+    }
+}
+
+pub fn encode_xpub(xpub: &ExtendedPubKey, encoding: &str) -> Result<String, String> {
+    let version = match slip132_version(encoding) {
+        Some(v) => v,
+        None => return Err(format!("Unknown extended key encoding {}", encoding)),
+    };
+    let mut data = base58::from_check(&xpub.to_string()).map_err(|e| e.to_string())?;
+    data[0..4].copy_from_slice(&version);
+    Ok(base58::check_encode_slice(&data))
+}
+
+// [d34db33f/84'/0'/0']
+pub fn key_origin(fingerprint: Fingerprint, path: &DerivationPath) -> String {
+    format!("[{}{}]", fingerprint, path.to_string().trim_start_matches('m'))
+}
+
+// d34db33f/84'/0'/0'
+fn parse_key_origin(origin: &str) -> Result<(Fingerprint, DerivationPath), String> {
+    let mut parts = origin.splitn(2, '/');
+    let fingerprint = match hex::decode(parts.next().unwrap_or("")) {
+        Ok(ref bytes) if bytes.len() == 4 => Fingerprint::from(&bytes[..]),
+        _ => return Err("Invalid key origin fingerprint".to_string()),
+    };
+    let path = match parts.next() {
+        Some(p) => DerivationPath::from_str(&format!("m/{}", p)).map_err(|e| e.to_string())?,
+        None => DerivationPath::from(vec![]),
+    };
+    Ok((fingerprint, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MNEMONIC: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+    const PASSWORD: &str = "password";
+
+    fn master(network: Network) -> Master {
+        Master::new_from_inline_mnemonic(MNEMONIC.to_string(), PASSWORD.to_string(), network, None).unwrap()
+    }
+
+    // BIP44, BIP49 and BIP84 test vectors
+    #[test]
+    fn standard_paths() {
+        let cases = [
+            (Network::Bitcoin, AccountAddressType::P2PKH, "m/44'/0'/0'/0/0", "1LqBGSKuX5yYUonjxT5qGfpUsXKYYWeabA"),
+            (Network::Testnet, AccountAddressType::P2SHWPKH, "m/49'/1'/0'/0/0", "2Mww8dCYPUpKHofjgcXcBCEGmniw9CoaiD2"),
+            (Network::Bitcoin, AccountAddressType::P2WPKH, "m/84'/0'/0'/0/0", "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu"),
+        ];
+        for (network, address_type, path, expected) in cases.iter() {
+            let master = master(*network);
+            let derived = master.child_path(PASSWORD, *address_type, 0, 0, 0, false).unwrap();
+            assert_eq!(derived, DerivationPath::from_str(path).unwrap());
+            let pk = master.get_child_pk(PASSWORD.to_string(), &derived).unwrap();
+            assert_eq!(to_address(*address_type, &pk, *network).to_string(), *expected);
+        }
+    }
+
+    // --legacy-paths gives the keys older versions derived through Account::new
+    #[test]
+    fn legacy_paths_match_sub_account_keys() {
+        let address_types = [AccountAddressType::P2PKH, AccountAddressType::P2SHWPKH, AccountAddressType::P2WPKH];
+        for &network in &[Network::Bitcoin, Network::Testnet, Network::Regtest] {
+            let master = master(network);
+            for &address_type in &address_types {
+                for &(n, m, k) in &[(0, 0, 0), (0, 1, 3), (2, 0, 7)] {
+                    let mut unlocker = Unlocker::new_for_master(&master.encrypted, PASSWORD).unwrap();
+                    let account = Account::new(&mut unlocker, address_type, n, m, 10).unwrap();
+                    let path = master.child_path(PASSWORD, address_type, n, m, k, true).unwrap();
+                    let pk = master.get_child_pk(PASSWORD.to_string(), &path).unwrap();
+                    assert_eq!(pk, account.compute_base_public_key(k).unwrap());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn group_scheme() {
+        let (group_threshold, groups) = parse_groups("2:family=2/3,lawyers=1/1,safe=3/5").unwrap();
+        assert_eq!(group_threshold, 2);
+        let parsed: Vec<(&str, u8, u8)> = groups.iter().map(|g| (g.name.as_str(), g.threshold, g.count)).collect();
+        assert_eq!(parsed, vec![("family", 2, 3), ("lawyers", 1, 1), ("safe", 3, 5)]);
+        let (_, groups) = parse_groups("1:2/3").unwrap();
+        assert_eq!(groups[0].name, "#1");
+    }
+
+    #[test]
+    fn invalid_group_schemes() {
+        for spec in &["", "2", "x:2/3", "1:2-3", "1:a=2/x", "1:0/3", "1:4/3", "1:2/17", "1:1/2", "0:2/3", "3:2/3,2/3"] {
+            assert!(parse_groups(spec).is_err(), "{} should be refused", spec);
+        }
+        let plain = |threshold, count| vec![ShareGroup { name: "shares".to_string(), threshold, count }];
+        assert!(check_groups(1, &plain(2, 3)).is_ok());
+        assert!(check_groups(1, &plain(1, 3)).is_err());
+        assert!(check_groups(1, &plain(3, 17)).is_err());
+        assert!(check_groups(1, &plain(0, 0)).is_err());
+    }
+
+    #[test]
+    fn select_more_shares_than_threshold() {
+        let seed = Seed(vec![0x42; 16]);
+        let shares = ShamirSecretSharing::generate(2, &[(2, 3), (1, 1), (3, 5)], &seed, None, 1).unwrap();
+        let selected = select_shares(&shares).unwrap();
+        assert_eq!(selected.len(), 3);
+        assert_eq!(ShamirSecretSharing::combine(&selected, None).unwrap().0, seed.0);
+        // Every share of the safe group and one of the family: a single complete group
+        let partial: Vec<Share> = shares.iter().filter(|s| s.group_index == 2 || (s.group_index == 0 && s.member_index == 0)).cloned().collect();
+        assert!(select_shares(&partial).is_none());
+        let safe_and_lawyers: Vec<Share> = shares.iter().filter(|s| s.group_index > 0).cloned().collect();
+        let selected = select_shares(&safe_and_lawyers).unwrap();
+        assert_eq!(selected.len(), 4);
+        assert_eq!(ShamirSecretSharing::combine(&selected, None).unwrap().0, seed.0);
+    }
+}
@@ -0,0 +1,262 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use bitcoin::{
+    network::constants::Network,
+    util::bip32::Fingerprint,
+};
+use qrcode::{Color, QrCode};
+
+/*
+ *  Printable paper backups: numbered word grid, fingerprint, descriptor QR code, birthday, print date and network.
+ *  The sheet is laid out once on an A4 page in millimeters and written as SVG or PDF,
+ *  or as plain text
+ */
+
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 20.0;
+const COLUMNS: usize = 4;
+const QR_SIZE: f32 = 70.0;
+const DESCRIPTOR_LINE: usize = 64;
+
+pub struct Sheet {
+    pub title: String,
+    pub words: Vec<String>,
+    pub fingerprint: Fingerprint,
+    pub descriptors: Vec<String>,
+    pub network: Network,
+    pub birthday: Option<u64>,
+    pub printed: String,
+}
+
+pub enum Format {
+    Svg,
+    Pdf,
+    Ascii,
+}
+
+impl Format {
+    // Explicit format, otherwise inferred from the file extension
+    pub fn from_args(format: Option<&String>, out: &str) -> Result<Format, String> {
+        let name = match format {
+            Some(f) => f.to_lowercase(),
+            None => out.rsplit('.').next().unwrap_or("").to_lowercase(),
+        };
+        match name.as_str() {
+            "svg" => Ok(Format::Svg),
+            "pdf" => Ok(Format::Pdf),
+            "ascii" | "txt" => Ok(Format::Ascii),
+            _ => Err(format!("Unknown backup format for {}, use svg, pdf or ascii", out)),
+        }
+    }
+}
+
+enum Item {
+    Text { x: f32, y: f32, size: f32, bold: bool, text: String },
+    Rect { x: f32, y: f32, w: f32, h: f32 },
+}
+
+pub fn render(sheet: &Sheet, format: Format) -> Result<Vec<u8>, String> {
+    let qr = QrCode::new(sheet.descriptors[0].as_bytes()).map_err(|e| e.to_string())?;
+    Ok(match format {
+        Format::Svg => to_svg(&layout(sheet, &qr)).into_bytes(),
+        Format::Pdf => to_pdf(&layout(sheet, &qr)),
+        Format::Ascii => to_ascii(sheet, &qr).into_bytes(),
+    })
+}
+
+// YYYY-MM-DD in UTC
+pub fn today() -> String {
+    let days = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() / 86400).unwrap_or(0) as i64;
+    // Civil date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// The print date is not the wallet creation date, rescans start from the birthday height
+fn header(sheet: &Sheet) -> Vec<(String, String)> {
+    let mut fields = vec![
+        ("Network".to_string(), sheet.network.to_string()),
+        ("Fingerprint".to_string(), sheet.fingerprint.to_string()),
+    ];
+    if let Some(height) = sheet.birthday {
+        fields.push(("Birthday height".to_string(), height.to_string()));
+    }
+    fields.push(("Printed".to_string(), sheet.printed.clone()));
+    fields
+}
+
+fn descriptor_lines(descriptor: &str) -> Vec<String> {
+    descriptor.as_bytes()
+        .chunks(DESCRIPTOR_LINE)
+        .map(|c| String::from_utf8_lossy(c).to_string())
+        .collect()
+}
+
+fn layout(sheet: &Sheet, qr: &QrCode) -> Vec<Item> {
+    let mut items: Vec<Item> = vec![];
+    let mut y = MARGIN + 8.0;
+    items.push(Item::Text { x: MARGIN, y, size: 8.0, bold: true, text: "Bitcoin wallet backup".to_string() });
+    y += 8.0;
+    items.push(Item::Text { x: MARGIN, y, size: 5.0, bold: true, text: sheet.title.clone() });
+    y += 4.0;
+    for (name, value) in header(sheet) {
+        y += 6.0;
+        items.push(Item::Text { x: MARGIN, y, size: 4.0, bold: true, text: format!("{}:", name) });
+        items.push(Item::Text { x: MARGIN + 30.0, y, size: 4.0, bold: false, text: value });
+    }
+
+    // Numbered down the columns, as read on a seed card
+    y += 6.0;
+    let rows = (sheet.words.len() + COLUMNS - 1) / COLUMNS;
+    let column_width = (PAGE_WIDTH - 2.0 * MARGIN) / COLUMNS as f32;
+    for (i, word) in sheet.words.iter().enumerate() {
+        let x = MARGIN + (i / rows) as f32 * column_width;
+        let row_y = y + 9.0 * (i % rows + 1) as f32;
+        items.push(Item::Rect { x, y: row_y - 6.0, w: column_width - 4.0, h: 0.2 });
+        items.push(Item::Text { x, y: row_y, size: 3.5, bold: false, text: format!("{:>2}.", i+1) });
+        items.push(Item::Text { x: x + 8.0, y: row_y, size: 4.5, bold: true, text: word.clone() });
+    }
+    y += 9.0 * rows as f32 + 12.0;
+
+    items.push(Item::Text { x: MARGIN, y, size: 4.0, bold: true, text: "Descriptors:".to_string() });
+    for descriptor in &sheet.descriptors {
+        for line in descriptor_lines(descriptor) {
+            y += 4.5;
+            items.push(Item::Text { x: MARGIN, y, size: 3.0, bold: false, text: line });
+        }
+        y += 2.0;
+    }
+    y += 4.0;
+
+    let width = qr.width();
+    let module = QR_SIZE / width as f32;
+    let x = (PAGE_WIDTH - QR_SIZE) / 2.0;
+    for (i, color) in qr.to_colors().iter().enumerate() {
+        if *color == Color::Dark {
+            items.push(Item::Rect {
+                x: x + (i % width) as f32 * module,
+                y: y + (i / width) as f32 * module,
+                w: module,
+                h: module,
+            });
+        }
+    }
+    y += QR_SIZE + 6.0;
+    items.push(Item::Text { x, y, size: 3.0, bold: false, text: "Receive descriptor".to_string() });
+    items
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn to_svg(items: &Vec<Item>) -> String {
+    let mut svg = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}mm\" height=\"{h}mm\" viewBox=\"0 0 {w} {h}\">\n<rect width=\"{w}\" height=\"{h}\" fill=\"white\"/>\n",
+        w = PAGE_WIDTH, h = PAGE_HEIGHT
+    );
+    for item in items {
+        match item {
+            Item::Text { x, y, size, bold, text } => svg.push_str(&format!(
+                "<text x=\"{:.2}\" y=\"{:.2}\" font-family=\"monospace\" font-size=\"{:.2}\" font-weight=\"{}\">{}</text>\n",
+                x, y, size, if *bold { "bold" } else { "normal" }, escape_xml(text)
+            )),
+            Item::Rect { x, y, w, h } => svg.push_str(&format!(
+                "<rect x=\"{:.3}\" y=\"{:.3}\" width=\"{:.3}\" height=\"{:.3}\" fill=\"black\"/>\n", x, y, w, h
+            )),
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn escape_pdf(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('(', "\\(").replace(')', "\\)")
+}
+
+// Single page PDF using the standard Courier fonts, coordinates are converted to points from the bottom left
+fn to_pdf(items: &Vec<Item>) -> Vec<u8> {
+    let pt = 72.0 / 25.4;
+    let mut content = String::new();
+    for item in items {
+        match item {
+            Item::Text { x, y, size, bold, text } => content.push_str(&format!(
+                "BT /{} {:.2} Tf {:.2} {:.2} Td ({}) Tj ET\n",
+                if *bold { "F2" } else { "F1" }, size * pt, x * pt, (PAGE_HEIGHT - y) * pt, escape_pdf(text)
+            )),
+            Item::Rect { x, y, w, h } => content.push_str(&format!(
+                "{:.3} {:.3} {:.3} {:.3} re f\n", x * pt, (PAGE_HEIGHT - y - h) * pt, w * pt, h * pt
+            )),
+        }
+    }
+    let objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+        format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] /Resources << /Font << /F1 4 0 R /F2 5 0 R >> >> /Contents 6 0 R >>",
+            PAGE_WIDTH * pt, PAGE_HEIGHT * pt
+        ),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Courier >>".to_string(),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Courier-Bold >>".to_string(),
+        format!("<< /Length {} >>\nstream\n{}endstream", content.len(), content),
+    ];
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets: Vec<usize> = vec![];
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.push_str(&format!("{} 0 obj\n{}\nendobj\n", i+1, object));
+    }
+    let xref = pdf.len();
+    pdf.push_str(&format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1));
+    for offset in offsets {
+        pdf.push_str(&format!("{:010} 00000 n \n", offset));
+    }
+    pdf.push_str(&format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref));
+    pdf.into_bytes()
+}
+
+fn to_ascii(sheet: &Sheet, qr: &QrCode) -> String {
+    let mut text = format!("BITCOIN WALLET BACKUP\n{}\n\n", sheet.title);
+    for (name, value) in header(sheet) {
+        text.push_str(&format!("{:<13}{}\n", format!("{}:", name), value));
+    }
+    text.push_str("\n");
+    let rows = (sheet.words.len() + COLUMNS - 1) / COLUMNS;
+    for row in 0..rows {
+        for column in 0..COLUMNS {
+            let i = column * rows + row;
+            if let Some(word) = sheet.words.get(i) {
+                text.push_str(&format!("{:>2}. {:<14}", i+1, word));
+            }
+        }
+        text.push_str("\n");
+    }
+    text.push_str("\nDescriptors:\n");
+    for descriptor in &sheet.descriptors {
+        for line in descriptor_lines(descriptor) {
+            text.push_str(&format!("{}\n", line));
+        }
+    }
+    text.push_str("\nReceive descriptor:\n");
+    let width = qr.width();
+    let colors = qr.to_colors();
+    // Two characters per module with a quiet zone, so the code keeps its aspect ratio
+    let quiet = 4;
+    for y in 0..width + 2 * quiet {
+        for x in 0..width + 2 * quiet {
+            let dark = x >= quiet && y >= quiet && x < width + quiet && y < width + quiet
+                && colors[(y - quiet) * width + x - quiet] == Color::Dark;
+            text.push_str(if dark { "##" } else { "  " });
+        }
+        text.push_str("\n");
+    }
+    text
+}
@@ -1,5 +1,6 @@
 use std::{
-    fs::File,
+    fs::{self, File, OpenOptions},
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
     path::Path,
     io::{
         Read,
@@ -12,34 +13,78 @@ use termion::{
     style,
 };
 
-pub fn write_to_file(filename: &str, bytes: &Vec<u8>) {
+// Key material is written to a 0600 temporary file, synced and renamed into place.
+// The previous version is kept as .bak
+pub fn write_to_file(filename: &str, bytes: &Vec<u8>) -> Result<(), String> {
     let path = Path::new(filename);
     let display = path.display();
-    let mut file = match File::create(&path) {
-        Err(why) => panic!("could not create {}: {}", display, why),
+    let tmp = format!("{}.tmp", filename);
+    if Path::new(&tmp).exists() {
+        fs::remove_file(&tmp).map_err(|why| format!("could not remove {}: {}", tmp, why))?;
+    }
+    let mut file = match OpenOptions::new().write(true).create_new(true).mode(0o600).open(&tmp) {
+        Err(why) => return Err(format!("could not create {}: {}", tmp, why)),
         Ok(file) => file,
     };
-    match file.write_all(bytes) {
-        Err(why) => panic!("could not write to {}: {}", display, why),
-        Ok(_) => (),
+    if let Err(why) = file.write_all(bytes).and_then(|_| file.sync_all()) {
+        return Err(format!("could not write to {}: {}", tmp, why));
+    }
+    if path.exists() {
+        let bak = format!("{}.bak", filename);
+        if let Err(why) = fs::copy(&path, &bak) {
+            return Err(format!("could not back up {}: {}", display, why));
+        }
+        fs::set_permissions(&bak, fs::Permissions::from_mode(0o600)).map_err(|why| format!("could not protect {}: {}", bak, why))?;
+    }
+    if let Err(why) = fs::rename(&tmp, &path) {
+        return Err(format!("could not replace {}: {}", display, why));
+    }
+    // Persist the rename itself
+    if let Some(dir) = path.parent() {
+        if let Ok(d) = File::open(if dir == Path::new("") { Path::new(".") } else { dir }) {
+            let _ = d.sync_all();
+        }
     }
+    Ok(())
 }
 
-pub fn read_from_file(filename: &str) -> Vec<u8> {
+// Single-use output such as a paper sheet: a new 0600 file, no .bak copy left behind
+pub fn create_private_file(filename: &str, bytes: &Vec<u8>) -> Result<(), String> {
+    let mut file = match OpenOptions::new().write(true).create_new(true).mode(0o600).open(filename) {
+        Err(why) => return Err(format!("could not create {}: {}", filename, why)),
+        Ok(file) => file,
+    };
+    file.write_all(bytes).and_then(|_| file.sync_all()).map_err(|why| format!("could not write to {}: {}", filename, why))
+}
+
+// Key files accessible by other users are refused unless forced
+pub fn check_permissions(filename: &str) -> Result<(), String> {
+    let metadata = fs::metadata(filename).map_err(|why| format!("could not open {}: {}", filename, why))?;
+    if metadata.permissions().mode() & 0o007 != 0 {
+        return Err(format!("{} is accessible by other users, restrict it with chmod 600 or use --insecure-permissions", filename));
+    }
+    Ok(())
+}
+
+pub fn read_from_file(filename: &str) -> Result<Vec<u8>, String> {
     let path = Path::new(filename);
     let display = path.display();
     let mut file = match File::open(&path) {
-        Err(why) => panic!("could not open {}: {}", display, why),
+        Err(why) => return Err(format!("could not open {}: {}", display, why)),
         Ok(file) => file,
     };
     let mut s = Vec::new();
     match file.read_to_end(&mut s) {
-        Err(why) => panic!("could not read {}: {}", display, why),
-        Ok(_) => s,
+        Err(why) => Err(format!("could not read {}: {}", display, why)),
+        Ok(_) => Ok(s),
     }
 }
 
-pub fn fatal_kill(message: &str) {
+pub fn file_exists(filename: &str) -> bool {
+    Path::new(filename).exists()
+}
+
+pub fn fatal_kill(message: &str) -> ! {
     println!("{}{}Error: {}{}{}{}", color::Fg(color::Red), style::Bold, style::Reset, color::Fg(color::Red), message, style::Reset);
     process::exit(1);
 }
@@ -0,0 +1,323 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    fs::{self, DirBuilder},
+    os::unix::fs::DirBuilderExt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use bitcoin::{
+    network::constants::Network,
+    util::bip32::ExtendedPubKey,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::crypto;
+use crate::keys::ImportedKey;
+use crate::utils;
+
+/*
+ *  Single wallet container: a plaintext header describing the file and an
+ *  encrypted payload with the seed and every piece of wallet state.
+ *  The header is not authenticated, its network is checked against the payload on load
+ */
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct WalletFile {
+    version: u32,
+    network: String,
+    payload: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Payload {
+    network: String,
+    master_public: ExtendedPubKey,
+    seed: String,
+    imported: Vec<ImportedKey>,
+    state: WalletState,
+}
+
+// Next unused receive and change indexes of an account in use
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AccountState {
+    pub purpose: u32,
+    pub account: u32,
+    pub next_receive: u32,
+    pub next_change: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct WalletState {
+    pub birthday: Option<u64>,
+    pub accounts: Vec<AccountState>,
+    pub labels: BTreeMap<String, String>,
+    pub descriptors: Vec<String>,
+}
+
+impl WalletState {
+    // Moves the next unused index past k, returns true for accounts not seen before
+    pub fn record_usage(&mut self, purpose: u32, account: u32, chain: u32, k: u32) -> bool {
+        let is_new = !self.accounts.iter().any(|a| a.purpose == purpose && a.account == account);
+        if is_new {
+            self.accounts.push(AccountState {
+                purpose,
+                account,
+                next_receive: 0,
+                next_change: 0,
+            });
+        }
+        let acc = self.accounts.iter_mut().find(|a| a.purpose == purpose && a.account == account).unwrap();
+        let next = match chain {
+            1 => &mut acc.next_change,
+            _ => &mut acc.next_receive,
+        };
+        if k >= *next {
+            *next = k + 1;
+        }
+        is_new
+    }
+}
+
+// $XDG_DATA_HOME/bitcoin-cli-wallet unless overridden, created on first use
+pub fn datadir(custom: Option<&String>) -> Result<PathBuf, String> {
+    let dir = match custom {
+        Some(d) => PathBuf::from(d),
+        None => match (env::var("XDG_DATA_HOME"), env::var("HOME")) {
+            (Ok(xdg), _) if xdg != "" => PathBuf::from(xdg).join("bitcoin-cli-wallet"),
+            (_, Ok(home)) => PathBuf::from(home).join(".local/share/bitcoin-cli-wallet"),
+            _ => return Err("Cannot find a data directory, specify one with --datadir".to_string()),
+        },
+    };
+    if !dir.exists() {
+        if let Err(e) = DirBuilder::new().recursive(true).mode(0o700).create(&dir) {
+            return Err(format!("could not create {}: {}", dir.display(), e));
+        }
+    }
+    Ok(dir)
+}
+
+// Labels are plain file names, so a wallet cannot point outside the data directory
+pub fn check_label(label: &str) -> Result<(), String> {
+    if label.is_empty() || label.contains('/') || label.starts_with('.') {
+        return Err(format!("Invalid label {}: it cannot be empty, contain '/' or start with '.', use --datadir to choose the directory", label));
+    }
+    Ok(())
+}
+
+pub fn prefix(datadir: &Path, label: &str) -> Result<String, String> {
+    check_label(label)?;
+    Ok(datadir.join(label).to_string_lossy().to_string())
+}
+
+// Labels of the wallets in the data directory, including the older two files format
+pub fn list(datadir: &Path) -> Result<Vec<String>, String> {
+    let entries = fs::read_dir(datadir).map_err(|e| format!("could not read {}: {}", datadir.display(), e))?;
+    let mut labels: Vec<String> = vec![];
+    for entry in entries {
+        let name = match entry {
+            Ok(e) => e.file_name().to_string_lossy().to_string(),
+            Err(_) => continue,
+        };
+        let label = if name.ends_with(".wallet") {
+            name.trim_end_matches(".wallet").to_string()
+        } else if name.ends_with("-pub") {
+            name.trim_end_matches("-pub").to_string()
+        } else {
+            continue;
+        };
+        if !labels.contains(&label) {
+            labels.push(label);
+        }
+    }
+    labels.sort();
+    Ok(labels)
+}
+
+fn names(prefix: &str) -> Vec<String> {
+    vec![filename(prefix), prefix.to_string(), format!("{}-pub", prefix), format!("{}-keys", prefix)]
+}
+
+// Key files of a wallet, in any of the formats
+pub fn key_files(prefix: &str) -> Vec<String> {
+    names(prefix)
+        .into_iter()
+        .filter(|f| utils::file_exists(f))
+        .collect()
+}
+
+// Every file belonging to a wallet, including backups of previous versions
+// and temporary files left by an interrupted write
+pub fn files(prefix: &str) -> Vec<String> {
+    let mut files = key_files(prefix);
+    for f in names(prefix) {
+        for extra in &[format!("{}.bak", f), format!("{}.tmp", f)] {
+            if utils::file_exists(extra) {
+                files.push(extra.clone());
+            }
+        }
+    }
+    files
+}
+
+pub fn rename(from: &str, to: &str) -> Result<(), String> {
+    if files(to).len() > 0 {
+        return Err(format!("A wallet already exists at {}", to));
+    }
+    let from_files = files(from);
+    if from_files.len() == 0 {
+        return Err(format!("Wallet {} not found", from));
+    }
+    for f in from_files {
+        let target = format!("{}{}", to, &f[from.len()..]);
+        fs::rename(&f, &target).map_err(|e| format!("could not rename {}: {}", f, e))?;
+    }
+    Ok(())
+}
+
+pub fn delete(prefix: &str) -> Result<(), String> {
+    for f in files(prefix) {
+        fs::remove_file(&f).map_err(|e| format!("could not delete {}: {}", f, e))?;
+    }
+    Ok(())
+}
+
+// Files still readable with a previous password: the older format and every backup,
+// only the container is kept
+pub fn delete_old_versions(prefix: &str) -> Result<Vec<String>, String> {
+    let old: Vec<String> = files(prefix).into_iter().filter(|f| *f != filename(prefix)).collect();
+    for f in &old {
+        fs::remove_file(f).map_err(|e| format!("could not delete {}: {}", f, e))?;
+    }
+    Ok(old)
+}
+
+pub fn filename(prefix: &str) -> String {
+    format!("{}.wallet", prefix)
+}
+
+pub fn exists(prefix: &str) -> bool {
+    utils::file_exists(&filename(prefix))
+}
+
+// Header fields that can be read without the password, the network is only confirmed by load
+pub fn read_header(prefix: &str) -> Result<(u32, Network), String> {
+    let bytes = utils::read_from_file(&filename(prefix))?;
+    let file: WalletFile = serde_json::from_slice(&bytes).map_err(|e| format!("Invalid wallet file: {}", e))?;
+    let network = Network::from_str(&file.network).map_err(|e| format!("Invalid wallet network: {}", e))?;
+    Ok((file.version, network))
+}
+
+pub fn load(
+    prefix: &str,
+    password: &str,
+) -> Result<(Network, Vec<u8>, ExtendedPubKey, Vec<ImportedKey>, WalletState), String> {
+    let bytes = utils::read_from_file(&filename(prefix))?;
+    let file: WalletFile = serde_json::from_slice(&bytes).map_err(|e| format!("Invalid wallet file: {}", e))?;
+    if file.version > SCHEMA_VERSION {
+        return Err(format!("Wallet file version {} is newer than supported version {}", file.version, SCHEMA_VERSION));
+    }
+    let network = Network::from_str(&file.network).map_err(|e| format!("Invalid wallet network: {}", e))?;
+    let encrypted = hex::decode(&file.payload).map_err(|_| "Invalid wallet file payload".to_string())?;
+    let decrypted = crypto::decrypt(&encrypted, password).map_err(|e| crypto::decrypt_error(&encrypted, e))?;
+    let payload: Payload = serde_json::from_slice(&decrypted).map_err(|_| crypto::content_error(&encrypted))?;
+    if payload.network != file.network {
+        return Err(format!("Wallet header network {} does not match the encrypted {}", file.network, payload.network));
+    }
+    let seed = hex::decode(&payload.seed).map_err(|_| "Invalid wallet file seed".to_string())?;
+    Ok((network, seed, payload.master_public, payload.imported, payload.state))
+}
+
+pub fn save(
+    prefix: &str,
+    password: &str,
+    network: Network,
+    encrypted_seed: &[u8],
+    master_public: &ExtendedPubKey,
+    imported: &Vec<ImportedKey>,
+    state: &WalletState,
+) -> Result<(), String> {
+    let payload = Payload {
+        network: network.to_string(),
+        master_public: master_public.clone(),
+        seed: hex::encode(encrypted_seed),
+        imported: imported.clone(),
+        state: state.clone(),
+    };
+    let serialized = serde_json::to_vec(&payload).map_err(|e| e.to_string())?;
+    let encrypted = crypto::encrypt(&serialized, password).map_err(|_| "Failed to encrypt wallet".to_string())?;
+    let file = WalletFile {
+        version: SCHEMA_VERSION,
+        network: network.to_string(),
+        payload: hex::encode(encrypted),
+    };
+    let bytes = serde_json::to_vec_pretty(&file).map_err(|e| e.to_string())?;
+    utils::write_to_file(&filename(prefix), &bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{
+        secp256k1::Secp256k1,
+        util::bip32::ExtendedPrivKey,
+    };
+
+    fn test_prefix(name: &str) -> String {
+        let dir = env::temp_dir().join(format!("bitcoin-cli-wallet-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        prefix(&dir, "test").unwrap()
+    }
+
+    fn save_test_wallet(prefix: &str) -> ExtendedPubKey {
+        let secp = Secp256k1::new();
+        let xpub = ExtendedPubKey::from_private(&secp, &ExtendedPrivKey::new_master(Network::Testnet, &[7u8; 32]).unwrap());
+        let mut state = WalletState::default();
+        state.birthday = Some(650000);
+        state.record_usage(84, 0, 0, 4);
+        state.labels.insert("address".to_string(), "note".to_string());
+        save(prefix, "password", Network::Testnet, &[1, 2, 3], &xpub, &vec![], &state).unwrap();
+        xpub
+    }
+
+    #[test]
+    fn save_and_load() {
+        let prefix = test_prefix("load");
+        let xpub = save_test_wallet(&prefix);
+        assert_eq!(read_header(&prefix).unwrap(), (SCHEMA_VERSION, Network::Testnet));
+        let (network, seed, master_public, imported, state) = load(&prefix, "password").unwrap();
+        assert_eq!(network, Network::Testnet);
+        assert_eq!(seed, vec![1, 2, 3]);
+        assert_eq!(master_public, xpub);
+        assert!(imported.is_empty());
+        assert_eq!(state.birthday, Some(650000));
+        assert_eq!(state.accounts[0].next_receive, 5);
+        assert_eq!(state.labels["address"], "note");
+        assert_eq!(load(&prefix, "wrong").err(), Some(crypto::WRONG_PASSWORD.to_string()));
+        delete(&prefix).unwrap();
+    }
+
+    #[test]
+    fn edited_network() {
+        let prefix = test_prefix("network");
+        save_test_wallet(&prefix);
+        let mut file: WalletFile = serde_json::from_slice(&utils::read_from_file(&filename(&prefix)).unwrap()).unwrap();
+        file.network = Network::Bitcoin.to_string();
+        utils::write_to_file(&filename(&prefix), &serde_json::to_vec(&file).unwrap()).unwrap();
+        assert_eq!(read_header(&prefix).unwrap().1, Network::Bitcoin);
+        assert!(load(&prefix, "password").is_err());
+        delete(&prefix).unwrap();
+    }
+
+    #[test]
+    fn interrupted_write() {
+        let prefix = test_prefix("tmp");
+        let tmp = format!("{}.tmp", filename(&prefix));
+        fs::write(&tmp, b"partial").unwrap();
+        assert_eq!(files(&prefix), vec![tmp.clone()]);
+        delete(&prefix).unwrap();
+        assert!(!utils::file_exists(&tmp));
+    }
+}
@@ -0,0 +1,251 @@
+use std::sync::OnceLock;
+use bitcoin_hashes::{sha256, Hash};
+use termion::{
+    color,
+    style,
+};
+
+use crate::io;
+
+/*
+ *  BIP39 english wordlist checks and recovery assistance for hand-written mnemonics
+ */
+
+const ENGLISH: &str = include_str!("english.txt");
+
+static WORDS: OnceLock<Vec<&'static str>> = OnceLock::new();
+
+pub fn words() -> &'static [&'static str] {
+    WORDS.get_or_init(|| ENGLISH.lines().collect())
+}
+
+fn index_of(word: &str) -> Option<usize> {
+    words().iter().position(|w| *w == word)
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..b.len()+1).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for j in 0..b.len() {
+            let cost = if ca == b[j] { 0 } else { 1 };
+            let next = (row[j+1] + 1).min(row[j] + 1).min(prev + cost);
+            prev = row[j+1];
+            row[j+1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+// Closest words, BIP39 words are unique by their first 4 letters so prefixes go first
+pub fn suggestions(word: &str) -> Vec<&'static str> {
+    let prefix: String = word.chars().take(4).collect();
+    let mut candidates: Vec<(usize, &str)> = words().iter().copied()
+        .map(|w| (if prefix.len() == 4 && w.starts_with(&prefix) { 0 } else { levenshtein(word, w) }, w))
+        .filter(|(d, _)| *d <= 2)
+        .collect();
+    candidates.sort_by_key(|(d, _)| *d);
+    candidates.into_iter().take(5).map(|(_, w)| w).collect()
+}
+
+fn valid_checksum(indexes: &[usize]) -> bool {
+    let total_bits = indexes.len() * 11;
+    let checksum_bits = total_bits / 33;
+    let entropy_bits = total_bits - checksum_bits;
+    let mut bits: Vec<bool> = Vec::with_capacity(total_bits);
+    for index in indexes {
+        for b in (0..11).rev() {
+            bits.push((index >> b) & 1 == 1);
+        }
+    }
+    let mut entropy = vec![0u8; entropy_bits / 8];
+    for (i, bit) in bits[0..entropy_bits].iter().enumerate() {
+        if *bit {
+            entropy[i / 8] |= 1 << (7 - i % 8);
+        }
+    }
+    let hash = sha256::Hash::hash(&entropy).into_inner();
+    (0..checksum_bits).all(|i| bits[entropy_bits + i] == ((hash[i / 8] >> (7 - i % 8)) & 1 == 1))
+}
+
+// 128 to 256 bits in steps of 32, and the 512 bits of the paranoid entropy level
+fn is_valid_length(n: usize) -> bool {
+    (n >= 12 && n <= 24 && n % 3 == 0) || n == 48
+}
+
+pub fn validate(mnemonic: &[&str]) -> Result<(), String> {
+    let mut unknown = vec![];
+    for (i, word) in mnemonic.iter().enumerate() {
+        if index_of(word) == None {
+            unknown.push(format!("#{} \"{}\"", i+1, word));
+        }
+    }
+    if unknown.len() > 0 {
+        return Err(format!("Unknown words: {}", unknown.join(", ")));
+    }
+    if !is_valid_length(mnemonic.len()) {
+        return Err(format!("Invalid number of words: {}", mnemonic.len()));
+    }
+    let indexes: Vec<usize> = mnemonic.iter().map(|w| index_of(w).unwrap()).collect();
+    if !valid_checksum(&indexes) {
+        return Err("Invalid mnemonic checksum, at least one word is wrong".to_string());
+    }
+    Ok(())
+}
+
+// Words that give a valid checksum at position i, keeping the rest of the indexes
+fn candidates_at(indexes: &[usize], i: usize) -> Vec<&'static str> {
+    let all = words();
+    let mut indexes = indexes.to_vec();
+    (0..all.len())
+        .filter(|&c| {
+            indexes[i] = c;
+            valid_checksum(&indexes)
+        })
+        .map(|c| all[c])
+        .collect()
+}
+
+pub fn valid_last_words(mnemonic: &[&str]) -> Vec<&'static str> {
+    let mut indexes: Vec<usize> = mnemonic.iter().map(|w| index_of(w).unwrap_or(0)).collect();
+    indexes.push(0);
+    let last = indexes.len() - 1;
+    candidates_at(&indexes, last)
+}
+
+// Replacement candidates for one wrong word, per position
+pub fn replace_one_word(mnemonic: &[&str]) -> Vec<(usize, Vec<&'static str>)> {
+    let indexes: Vec<usize> = mnemonic.iter().map(|w| index_of(w).unwrap_or(0)).collect();
+    let unknown: Vec<usize> = (0..mnemonic.len()).filter(|&i| index_of(mnemonic[i]) == None).collect();
+    let positions: Vec<usize> = match unknown.len() {
+        0 => (0..mnemonic.len()).collect(),
+        1 => unknown,
+        _ => return vec![],
+    };
+    positions.into_iter().map(|i| (i, candidates_at(&indexes, i))).collect()
+}
+
+// Candidates for one missing word, per insert position
+pub fn insert_missing_word(mnemonic: &[&str]) -> Vec<(usize, Vec<&'static str>)> {
+    let indexes: Vec<usize> = match mnemonic.iter().map(|w| index_of(w)).collect::<Option<Vec<usize>>>() {
+        Some(i) => i,
+        None => return vec![],
+    };
+    (0..indexes.len()+1)
+        .map(|i| {
+            let mut with_missing = indexes.clone();
+            with_missing.insert(i, 0);
+            (i, candidates_at(&with_missing, i))
+        })
+        .collect()
+}
+
+fn show_candidates(candidates: &Vec<(usize, Vec<&str>)>) {
+    for (i, words) in candidates {
+        if words.len() > 0 {
+            println!("{}#{}:{} {}", style::Bold, i+1, style::Reset, words.join(" "));
+        }
+    }
+}
+
+// Diagnoses an invalid mnemonic and offers to brute force it
+pub fn assist(mnemonic: &[&str]) {
+    for (i, word) in mnemonic.iter().enumerate() {
+        if index_of(word) == None {
+            let close = suggestions(word);
+            match close.len() {
+                0 => println!("{}#{} \"{}\" is not a BIP39 word{}", color::Fg(color::Yellow), i+1, word, style::Reset),
+                _ => println!("{}#{} \"{}\" is not a BIP39 word, did you mean: {}{}", color::Fg(color::Yellow), i+1, word, close.join(", "), style::Reset),
+            }
+        }
+    }
+    let n = mnemonic.len();
+    if is_valid_length(n + 1) {
+        if io::confirm("One word seems to be missing, look for it?") {
+            show_candidates(&insert_missing_word(mnemonic));
+        }
+    } else if is_valid_length(n) {
+        if io::confirm("Look for one wrong word?") {
+            show_candidates(&replace_one_word(mnemonic));
+        }
+        if io::confirm("List the valid last words for the first words?") {
+            println!("{}", valid_last_words(&mnemonic[0..n-1]).join(" "));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use bitcoin_wallet::mnemonic::Mnemonic;
+
+    #[test]
+    fn round_trip_every_length() {
+        for &bytes in &[16, 20, 24, 28, 32, 64] {
+            let entropy: Vec<u8> = (0..bytes).map(|i| (i * 37 + 11) as u8).collect();
+            let mnemonic = Mnemonic::new(&entropy).unwrap().to_string();
+            let words: Vec<&str> = mnemonic.split(" ").collect();
+            assert_eq!(words.len(), bytes * 3 / 4);
+            assert_eq!(validate(&words), Ok(()));
+            assert_eq!(Mnemonic::from_str(&mnemonic).unwrap().to_string(), mnemonic);
+        }
+    }
+
+    #[test]
+    fn reject_bad_length_and_checksum() {
+        let mnemonic = Mnemonic::new(&[0u8; 16]).unwrap().to_string();
+        let words: Vec<&str> = mnemonic.split(" ").collect();
+        assert!(validate(&words[0..11]).is_err());
+        let mut wrong = words.clone();
+        wrong[11] = if words[11] == "zoo" { "abandon" } else { "zoo" };
+        assert!(validate(&wrong).is_err());
+    }
+
+    const ABANDON: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    #[test]
+    fn suggest_misspelled_words() {
+        assert_eq!(suggestions("abandn")[0], "abandon");
+        assert!(suggestions("abuot").contains(&"about"));
+        assert!(suggestions("qqqqqq").is_empty());
+    }
+
+    #[test]
+    fn find_missing_word() {
+        let words: Vec<&str> = ABANDON.split(" ").collect();
+        for &missing in &[4, 11] {
+            let mut incomplete = words.clone();
+            incomplete.remove(missing);
+            let candidates = insert_missing_word(&incomplete);
+            assert_eq!(candidates.len(), 12);
+            assert!(candidates[missing].1.contains(&words[missing]));
+        }
+    }
+
+    #[test]
+    fn find_wrong_word() {
+        let words: Vec<&str> = ABANDON.split(" ").collect();
+        let mut wrong = words.clone();
+        wrong[3] = "xyz";
+        let candidates = replace_one_word(&wrong);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].0, 3);
+        assert!(candidates[0].1.contains(&"abandon"));
+        wrong[3] = "zoo";
+        let candidates = replace_one_word(&wrong);
+        assert_eq!(candidates.len(), 12);
+        assert!(candidates[3].1.contains(&"abandon"));
+    }
+
+    #[test]
+    fn last_words() {
+        let words: Vec<&str> = ABANDON.split(" ").collect();
+        let last = valid_last_words(&words[0..11]);
+        // 7 free bits and a 4 bits checksum
+        assert_eq!(last.len(), 128);
+        assert!(last.contains(&"about"));
+    }
+}